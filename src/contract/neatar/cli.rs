@@ -0,0 +1,330 @@
+use anyhow::anyhow;
+use neatar::identicon::{self, Layout};
+use std::io::Write;
+
+const USAGE: &str = "Usage:
+  neatar svg (--account <account_id> | --seed <hex>) [--size <pixels>] [--layout compact|full] [--output <file>]
+  neatar png (--account <account_id> | --seed <hex> | --svg <file>) [--size <pixels>] [--layout compact|full] [--output <file>]
+  neatar scheme (--account <account_id> | --seed <hex>)
+  neatar token-id --file <file>
+  neatar decode --data <data:image/...;base64,...> [--output <file>]
+
+`--account` gives the identity avatar of the account, `--svg` rasterizes an identicon made by the contract,
+`decode` unpacks an avatar returned by `avatar_of`.";
+
+const DEFAULT_SIZE: u32 = 64;
+
+/// Options given as `--name value` pairs
+struct Options(Vec<(String, String)>);
+
+impl Options {
+    fn parse(args: &[String]) -> anyhow::Result<Self> {
+        let mut options = Vec::new();
+        let mut args = args.iter();
+        while let Some(name) = args.next() {
+            let name = name
+                .strip_prefix("--")
+                .ok_or_else(|| anyhow!("unexpected argument {}", name))?;
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow!("missing value of --{}", name))?;
+            options.push((name.to_string(), value.to_string()));
+        }
+        Ok(Self(options))
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn require(&self, name: &str) -> anyhow::Result<&str> {
+        self.get(name).ok_or_else(|| anyhow!("missing --{}", name))
+    }
+
+    fn size(&self) -> anyhow::Result<Option<u32>> {
+        match self.get("size") {
+            Some(size) => Ok(Some(size.parse()?)),
+            None => Ok(None),
+        }
+    }
+
+    fn layout(&self) -> anyhow::Result<Layout> {
+        match self.get("layout") {
+            None | Some("compact") => Ok(Layout::Compact),
+            Some("full") => Ok(Layout::Full),
+            Some(layout) => Err(anyhow!("unknown layout {}", layout)),
+        }
+    }
+
+    /// Identicon seed, the account id is used as is like the identity avatar
+    fn seed(&self) -> anyhow::Result<Vec<u8>> {
+        match (self.get("account"), self.get("seed")) {
+            (Some(account_id), None) => Ok(account_id.as_bytes().to_vec()),
+            (None, Some(seed)) => Ok(hex::decode(seed)?),
+            _ => Err(anyhow!("expected either --account or --seed")),
+        }
+    }
+
+    fn write(&self, out: &mut dyn Write, data: &[u8]) -> anyhow::Result<()> {
+        match self.get("output") {
+            Some(path) => std::fs::write(path, data)?,
+            None => out.write_all(data)?,
+        }
+        Ok(())
+    }
+}
+
+fn svg(options: &Options, out: &mut dyn Write) -> anyhow::Result<()> {
+    let seed = options.seed()?;
+    let svg = match options.size()? {
+        Some(size) => identicon::make_sized(&seed, options.layout()?, size),
+        None => identicon::make(&seed, options.layout()?),
+    };
+    options.write(out, svg.as_bytes())
+}
+
+fn png(options: &Options, out: &mut dyn Write) -> anyhow::Result<()> {
+    let size = options.size()?.unwrap_or(DEFAULT_SIZE);
+    let png = match options.get("svg") {
+        Some(path) => identicon::png_from_svg(&std::fs::read_to_string(path)?, size)?,
+        None => identicon::png_from_vec(&options.seed()?, size, options.layout()?)?,
+    };
+    options.write(out, &png)
+}
+
+fn scheme(options: &Options, out: &mut dyn Write) -> anyhow::Result<()> {
+    let scheme = identicon::scheme_from_vec(&options.seed()?);
+    let colors: Vec<String> = scheme
+        .colors
+        .into_iter()
+        .map(identicon::rgba_to_hex)
+        .collect();
+    writeln!(out, "scheme: {}", scheme.name)?;
+    writeln!(out, "rotation: {}", scheme.rotation)?;
+    writeln!(out, "colors: {}", colors.join(" "))?;
+    Ok(())
+}
+
+fn token_id(options: &Options, out: &mut dyn Write) -> anyhow::Result<()> {
+    let data = std::fs::read(options.require("file")?)?;
+    writeln!(out, "{}", neatar::token_id_of(&data))?;
+    Ok(())
+}
+
+fn decode(options: &Options, out: &mut dyn Write) -> anyhow::Result<()> {
+    let data = options.require("data")?;
+    let media = data
+        .strip_prefix("data:image/")
+        .ok_or_else(|| anyhow!("expected data:image/ uri"))?;
+    let (media_type, data) =
+        neatar::unpack_data_image(media).ok_or_else(|| anyhow!("expected base64 data"))?;
+    eprintln!("media type: image/{}", media_type);
+    options.write(out, &data)
+}
+
+fn run(args: &[String], out: &mut dyn Write) -> anyhow::Result<()> {
+    let (command, args) = args
+        .split_first()
+        .ok_or_else(|| anyhow!("missing command"))?;
+    let options = Options::parse(args)?;
+    match command.as_str() {
+        "svg" => svg(&options, out),
+        "png" => png(&options, out),
+        "scheme" => scheme(&options, out),
+        "token-id" => token_id(&options, out),
+        "decode" => decode(&options, out),
+        _ => Err(anyhow!("unknown command {}", command)),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(error) = run(&args, &mut std::io::stdout()) {
+        eprintln!("error: {}\n\n{}", error, USAGE);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod unit {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn run_output(command: &[&str]) -> anyhow::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        run(&args(command), &mut out)?;
+        Ok(out)
+    }
+
+    fn temp_path(name: &str) -> String {
+        let name = format!("neatar-cli-{}-{}", std::process::id(), name);
+        std::env::temp_dir()
+            .join(name)
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = Options::parse(&args(&["--size", "16", "--layout", "full"])).unwrap();
+        assert_eq!(Some(16), options.size().unwrap());
+        assert_eq!(Layout::Full, options.layout().unwrap());
+        assert_eq!(None, options.get("output"));
+        assert_eq!(
+            "missing --output",
+            options.require("output").unwrap_err().to_string()
+        );
+
+        let options = Options::parse(&[]).unwrap();
+        assert_eq!(None, options.size().unwrap());
+        assert_eq!(Layout::Compact, options.layout().unwrap());
+    }
+
+    #[test]
+    fn test_parse_options_invalid() {
+        assert_eq!(
+            "missing value of --size",
+            Options::parse(&args(&["--size"]))
+                .err()
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "unexpected argument size",
+            Options::parse(&args(&["size", "16"]))
+                .err()
+                .unwrap()
+                .to_string()
+        );
+        let options = Options::parse(&args(&["--size", "big", "--layout", "round"])).unwrap();
+        assert!(options.size().is_err());
+        assert_eq!(
+            "unknown layout round",
+            options.layout().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_seed() {
+        let options = Options::parse(&args(&["--account", "alice.near"])).unwrap();
+        assert_eq!(b"alice.near".to_vec(), options.seed().unwrap());
+        let options = Options::parse(&args(&["--seed", "0102"])).unwrap();
+        assert_eq!(vec![1, 2], options.seed().unwrap());
+
+        for command in [vec![], vec!["--account", "alice.near", "--seed", "0102"]] {
+            let options = Options::parse(&args(&command)).unwrap();
+            assert_eq!(
+                "expected either --account or --seed",
+                options.seed().unwrap_err().to_string()
+            );
+        }
+        let options = Options::parse(&args(&["--seed", "xyz"])).unwrap();
+        assert!(options.seed().is_err());
+    }
+
+    #[test]
+    fn test_run_invalid() {
+        assert_eq!("missing command", run_output(&[]).unwrap_err().to_string());
+        assert_eq!(
+            "unknown command jpg",
+            run_output(&["jpg"]).unwrap_err().to_string()
+        );
+        assert_eq!(
+            "missing --file",
+            run_output(&["token-id"]).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_svg() {
+        assert_eq!(
+            identicon::make(&[1], Layout::Compact).into_bytes(),
+            run_output(&["svg", "--seed", "01"]).unwrap()
+        );
+        assert_eq!(
+            identicon::make_sized(b"alice.near", Layout::Full, 16).into_bytes(),
+            run_output(&[
+                "svg",
+                "--account",
+                "alice.near",
+                "--layout",
+                "full",
+                "--size",
+                "16"
+            ])
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_png() {
+        assert_eq!(
+            identicon::png_from_vec(&[1], DEFAULT_SIZE, Layout::Compact).unwrap(),
+            run_output(&["png", "--seed", "01"]).unwrap()
+        );
+
+        let path = temp_path("png.svg");
+        std::fs::write(&path, identicon::make(&[1], Layout::Full)).unwrap();
+        let png = run_output(&["png", "--svg", &path, "--size", "16"]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            identicon::png_from_vec(&[1], 16, Layout::Full).unwrap(),
+            png.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_scheme() {
+        let scheme = identicon::scheme_from_vec(&[1]);
+        let output = String::from_utf8(run_output(&["scheme", "--seed", "01"]).unwrap()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(3, lines.len());
+        assert_eq!(format!("scheme: {}", scheme.name), lines[0]);
+        assert_eq!(format!("rotation: {}", scheme.rotation), lines[1]);
+        let colors: Vec<String> = scheme
+            .colors
+            .into_iter()
+            .map(identicon::rgba_to_hex)
+            .collect();
+        assert_eq!(format!("colors: {}", colors.join(" ")), lines[2]);
+    }
+
+    #[test]
+    fn test_token_id_decode() {
+        let svg = identicon::make(b"alice.near", Layout::Compact);
+        let data = format!("data:image/svg+xml;base64,{}", base64::encode(&svg));
+        assert_eq!(
+            svg.clone().into_bytes(),
+            run_output(&["decode", "--data", &data]).unwrap()
+        );
+
+        let path = temp_path("decoded.svg");
+        run_output(&["decode", "--data", &data, "--output", &path]).unwrap();
+        let token_id = run_output(&["token-id", "--file", &path]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            format!("{}\n", neatar::token_id_of(svg.as_bytes())).into_bytes(),
+            token_id.unwrap()
+        );
+
+        assert_eq!(
+            "expected data:image/ uri",
+            run_output(&["decode", "--data", "svg+xml;base64,AA=="])
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "expected base64 data",
+            run_output(&["decode", "--data", "data:image/svg+xml,<svg/>"])
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
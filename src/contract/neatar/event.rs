@@ -0,0 +1,128 @@
+//! Events in the NEP-297 format, `nep171` for token lifecycle and
+//! `neatar` for avatar changes.
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json;
+use near_sdk::{log, AccountId};
+
+const NFT_STANDARD: &str = "nep171";
+const NFT_VERSION: &str = "1.0.0";
+const NEATAR_STANDARD: &str = "neatar";
+const NEATAR_VERSION: &str = "1.0.0";
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a, T: Serialize> {
+    standard: &'a str,
+    version: &'a str,
+    event: &'a str,
+    data: [T; 1],
+}
+
+fn emit<T: Serialize>(standard: &str, version: &str, event: &str, data: T) {
+    let event = EventLog {
+        standard,
+        version,
+        event,
+        data: [data],
+    };
+    log!(
+        "EVENT_JSON:{}",
+        serde_json::to_string(&event).unwrap_or_default()
+    );
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NftMint<'a> {
+    owner_id: &'a AccountId,
+    token_ids: [&'a TokenId; 1],
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NftBurn<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    authorized_id: Option<&'a AccountId>,
+    owner_id: &'a AccountId,
+    token_ids: [&'a TokenId; 1],
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NftTransfer<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    authorized_id: Option<&'a AccountId>,
+    old_owner_id: &'a AccountId,
+    new_owner_id: &'a AccountId,
+    token_ids: [&'a TokenId; 1],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memo: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct AvatarActivate<'a> {
+    account_id: &'a AccountId,
+    token_id: Option<&'a TokenId>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct AvatarRegenerate<'a> {
+    account_id: &'a AccountId,
+    old_token_id: &'a TokenId,
+    new_token_id: &'a TokenId,
+}
+
+pub fn nft_mint(owner_id: &AccountId, token_id: &TokenId) {
+    let data = NftMint {
+        owner_id,
+        token_ids: [token_id],
+    };
+    emit(NFT_STANDARD, NFT_VERSION, "nft_mint", data);
+}
+
+pub fn nft_burn(owner_id: &AccountId, token_id: &TokenId, authorized_id: Option<&AccountId>) {
+    let data = NftBurn {
+        authorized_id,
+        owner_id,
+        token_ids: [token_id],
+    };
+    emit(NFT_STANDARD, NFT_VERSION, "nft_burn", data);
+}
+
+pub fn nft_transfer(
+    old_owner_id: &AccountId,
+    new_owner_id: &AccountId,
+    token_id: &TokenId,
+    authorized_id: Option<&AccountId>,
+    memo: Option<&str>,
+) {
+    let data = NftTransfer {
+        authorized_id,
+        old_owner_id,
+        new_owner_id,
+        token_ids: [token_id],
+        memo,
+    };
+    emit(NFT_STANDARD, NFT_VERSION, "nft_transfer", data);
+}
+
+/// The account chose another avatar, `None` when it has none left.
+pub fn avatar_activate(account_id: &AccountId, token_id: Option<&TokenId>) {
+    let data = AvatarActivate {
+        account_id,
+        token_id,
+    };
+    emit(NEATAR_STANDARD, NEATAR_VERSION, "avatar_activate", data);
+}
+
+pub fn avatar_regenerate(account_id: &AccountId, old_token_id: &TokenId, new_token_id: &TokenId) {
+    let data = AvatarRegenerate {
+        account_id,
+        old_token_id,
+        new_token_id,
+    };
+    emit(NEATAR_STANDARD, NEATAR_VERSION, "avatar_regenerate", data);
+}
@@ -1,16 +1,46 @@
 use anyhow::anyhow;
 use blake2_rfc::blake2b::blake2b;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
 use palette::{FromColor, FromComponent, Hsl, RgbHue, Srgb};
 use svg::node::element;
 use svg::Document;
 
+/// Half size of the reference identicon, geometry is calculated for it and scaled
 const HALF_SVG: i32 = 32;
 pub const FOREGROUND_COLOR: [u8; 4] = [238, 238, 238, 255];
 
-pub fn make(into_id: &[u8]) -> String {
-    svg_from_vec(into_id, HALF_SVG)
-        .to_string()
-        .replace("\n", "")
+/// Set of circles drawn around the center
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum Layout {
+    /// 11 circles, the layout of the first minted tokens
+    Compact,
+    /// 19 circles, the complete identicon
+    Full,
+}
+
+/// Indexes of the full layout circles kept in the compact layout
+const COMPACT_CIRCLES: [usize; 11] = [3, 4, 5, 6, 7, 12, 13, 14, 15, 16, 18];
+
+pub fn make(into_id: &[u8], layout: Layout) -> String {
+    render(svg_from_vec(into_id, HALF_SVG as f64, layout))
+}
+
+/// Same as `make` with `width` and `height` set to `size` pixels
+pub fn make_sized(into_id: &[u8], layout: Layout, size: u32) -> String {
+    let halfsize = size as f64 / 2.0;
+    render(
+        svg_from_vec(into_id, halfsize, layout)
+            .set("width", size)
+            .set("height", size),
+    )
+}
+
+fn render(document: Document) -> String {
+    document.to_string().replace("\n", "")
 }
 
 /// Function to choose the coloring scheme based on value d.
@@ -34,6 +64,7 @@ fn choose_scheme(schemes: Vec<SchemeElement>, d: u32) -> anyhow::Result<SchemeEl
 
 /// Struct to store default coloring schemes
 struct SchemeElement {
+    name: &'static str,
     freq: u8,
     colors: [usize; 19],
 }
@@ -42,39 +73,39 @@ struct SchemeElement {
 fn default_schemes() -> Vec<SchemeElement> {
     vec![
         SchemeElement {
-            // "target"
+            name: "target",
             freq: 1,
             colors: [
                 0, 28, 0, 0, 28, 0, 0, 28, 0, 0, 28, 0, 0, 28, 0, 0, 28, 0, 1,
             ],
         },
         SchemeElement {
-            // "cube",
+            name: "cube",
             freq: 20,
             colors: [0, 1, 3, 2, 4, 3, 0, 1, 3, 2, 4, 3, 0, 1, 3, 2, 4, 3, 5],
         },
         SchemeElement {
-            // "quazar",
+            name: "quazar",
             freq: 16,
             colors: [1, 2, 3, 1, 2, 4, 5, 5, 4, 1, 2, 3, 1, 2, 4, 5, 5, 4, 0],
         },
         SchemeElement {
-            // "flower",
+            name: "flower",
             freq: 32,
             colors: [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 3],
         },
         SchemeElement {
-            // "cyclic",
+            name: "cyclic",
             freq: 32,
             colors: [0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6],
         },
         SchemeElement {
-            // "vmirror",
+            name: "vmirror",
             freq: 128,
             colors: [0, 1, 2, 3, 4, 5, 3, 4, 2, 0, 1, 6, 7, 8, 9, 7, 8, 6, 10],
         },
         SchemeElement {
-            // "hmirror",
+            name: "hmirror",
             freq: 128,
             colors: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 8, 6, 7, 5, 3, 4, 2, 11],
         },
@@ -83,14 +114,15 @@ fn default_schemes() -> Vec<SchemeElement> {
 
 /// Struct to store information about circle center position
 /// For 19-circle icons circle positions are set as defaults
+#[derive(Clone, Copy)]
 struct CirclePosition {
-    x_center: i32,
-    y_center: i32,
+    x_center: f64,
+    y_center: f64,
 }
 
 /// Helper function to transform RGBA [u8; 4] color needed for png into
 /// hex string color needed for svg
-fn rgba_to_hex(rgba_color: [u8; 4]) -> String {
+pub fn rgba_to_hex(rgba_color: [u8; 4]) -> String {
     format!(
         "#{}",
         hex::encode(vec![rgba_color[0], rgba_color[1], rgba_color[2]])
@@ -98,131 +130,316 @@ fn rgba_to_hex(rgba_color: [u8; 4]) -> String {
 }
 
 /// Function to set default positions of small circles in 19-circle icon
-/// a is center_to_center distance
-fn position_circle_set(a: i32) -> Vec<CirclePosition> {
-    let b = ((a as f64) * 3f64.sqrt() / 2.0).round() as i32;
+/// a is center_to_center distance, b is horizontal distance between columns
+fn position_circle_set(a: f64, b: f64) -> Vec<CirclePosition> {
     vec![
-        // CirclePosition {
-        //     x_center: 0,
-        //     y_center: -2 * a,
-        // },
-        // CirclePosition {
-        //     x_center: 0,
-        //     y_center: -a,
-        // },
-        // CirclePosition {
-        //     x_center: -b,
-        //     y_center: -3 * a / 2,
-        // },
         CirclePosition {
-            x_center: -2 * b,
+            x_center: 0.0,
+            y_center: -2.0 * a,
+        },
+        CirclePosition {
+            x_center: 0.0,
             y_center: -a,
         },
         CirclePosition {
             x_center: -b,
-            y_center: -a / 2,
+            y_center: -1.5 * a,
         },
         CirclePosition {
-            x_center: -2 * b,
-            y_center: 0,
+            x_center: -2.0 * b,
+            y_center: -a,
         },
         CirclePosition {
-            x_center: -2 * b,
+            x_center: -b,
+            y_center: -a / 2.0,
+        },
+        CirclePosition {
+            x_center: -2.0 * b,
+            y_center: 0.0,
+        },
+        CirclePosition {
+            x_center: -2.0 * b,
             y_center: a,
         },
         CirclePosition {
             x_center: -b,
-            y_center: a / 2,
+            y_center: a / 2.0,
+        },
+        CirclePosition {
+            x_center: -b,
+            y_center: 1.5 * a,
+        },
+        CirclePosition {
+            x_center: 0.0,
+            y_center: 2.0 * a,
+        },
+        CirclePosition {
+            x_center: 0.0,
+            y_center: a,
+        },
+        CirclePosition {
+            x_center: b,
+            y_center: 1.5 * a,
         },
-        // CirclePosition {
-        //     x_center: -b,
-        //     y_center: 3 * a / 2,
-        // },
-        // CirclePosition {
-        //     x_center: 0,
-        //     y_center: 2 * a,
-        // },
-        // CirclePosition {
-        //     x_center: 0,
-        //     y_center: a,
-        // },
-        // CirclePosition {
-        //     x_center: b,
-        //     y_center: 3 * a / 2,
-        // },
         CirclePosition {
-            x_center: 2 * b,
+            x_center: 2.0 * b,
             y_center: a,
         },
         CirclePosition {
             x_center: b,
-            y_center: a / 2,
+            y_center: a / 2.0,
         },
         CirclePosition {
-            x_center: 2 * b,
-            y_center: 0,
+            x_center: 2.0 * b,
+            y_center: 0.0,
         },
         CirclePosition {
-            x_center: 2 * b,
+            x_center: 2.0 * b,
             y_center: -a,
         },
         CirclePosition {
             x_center: b,
-            y_center: -a / 2,
+            y_center: -a / 2.0,
         },
-        // CirclePosition {
-        //     x_center: b,
-        //     y_center: -3 * a / 2,
-        // },
         CirclePosition {
-            x_center: 0,
-            y_center: 0,
+            x_center: b,
+            y_center: -1.5 * a,
+        },
+        CirclePosition {
+            x_center: 0.0,
+            y_center: 0.0,
         },
     ]
 }
 
-pub fn svg_from_vec(into_id: &[u8], halfsize: i32) -> Document {
+/// Function to pick positions of the layout circles,
+/// circles are colored in order of this list
+fn position_layout(a: f64, layout: Layout) -> Vec<CirclePosition> {
+    let b = a * 3f64.sqrt() / 2.0;
+    match layout {
+        Layout::Full => position_circle_set(a, b),
+        // the first tokens were drawn with the distance between columns rounded
+        Layout::Compact => {
+            let positions = position_circle_set(a, b.round());
+            COMPACT_CIRCLES.iter().map(|&i| positions[i]).collect()
+        }
+    }
+}
+
+/// Function to make svg document with viewBox centered at zero,
+/// `halfsize` is the radius of the background circle
+pub fn svg_from_vec(into_id: &[u8], halfsize: f64, layout: Layout) -> Document {
     let mut document = Document::new().set(
         "viewBox",
-        (-halfsize, -halfsize, 2 * halfsize, 2 * halfsize),
+        (-halfsize, -halfsize, 2.0 * halfsize, 2.0 * halfsize),
     );
     let colors = get_colors_from_vec(into_id);
-    let data = calculate_svg_data(halfsize, colors);
+    let data = calculate_svg_data(halfsize, colors, layout);
     for x in data.into_iter() {
         document = document.add(x);
     }
     document
 }
 
-/// Function to calculate svg file contents (using element::Circle from svg crate)
-pub fn calculate_svg_data(big_radius: i32, colors: Vec<[u8; 4]>) -> Vec<element::Circle> {
-    let mut out: Vec<element::Circle> = Vec::with_capacity(20);
-    out.push(
-        element::Circle::new()
-            .set("cx", 0)
-            .set("cy", 0)
-            .set("r", big_radius)
-            .set("fill", rgba_to_hex(FOREGROUND_COLOR))
-            .set("stroke", "none"),
-    );
-    let small_radius = big_radius / 32 * 5;
-    let center_to_center = big_radius / 8 * 3;
-    let positions = position_circle_set(center_to_center);
+/// Struct to store a circle ready to be drawn
+pub struct Circle {
+    pub x_center: f64,
+    pub y_center: f64,
+    pub radius: f64,
+    pub color: [u8; 4],
+}
+
+/// Function to calculate circles of the icon, the background circle goes first
+/// Positions are taken from the reference size and scaled to `big_radius`,
+/// so coordinates become fractional for sizes other than the reference one
+pub fn calculate_circles(big_radius: f64, colors: Vec<[u8; 4]>, layout: Layout) -> Vec<Circle> {
+    let mut out: Vec<Circle> = Vec::with_capacity(20);
+    out.push(Circle {
+        x_center: 0.0,
+        y_center: 0.0,
+        radius: big_radius,
+        color: FOREGROUND_COLOR,
+    });
+    let scale = big_radius / HALF_SVG as f64;
+    let small_radius = (HALF_SVG / 32 * 5) as f64 * scale;
+    let center_to_center = HALF_SVG / 8 * 3;
+    let positions = position_layout(center_to_center as f64, layout);
     for (i, position) in positions.iter().enumerate() {
-        out.push(
+        out.push(Circle {
+            x_center: position.x_center * scale,
+            y_center: position.y_center * scale,
+            radius: small_radius,
+            color: colors[i],
+        });
+    }
+    out
+}
+
+/// Function to calculate svg file contents (using element::Circle from svg crate)
+pub fn calculate_svg_data(
+    big_radius: f64,
+    colors: Vec<[u8; 4]>,
+    layout: Layout,
+) -> Vec<element::Circle> {
+    calculate_circles(big_radius, colors, layout)
+        .into_iter()
+        .map(|circle| {
             element::Circle::new()
-                .set("cx", position.x_center)
-                .set("cy", position.y_center)
-                .set("r", small_radius)
-                .set("fill", rgba_to_hex(colors[i]))
-                .set("stroke", "none"),
-        );
+                .set("cx", circle.x_center)
+                .set("cy", circle.y_center)
+                .set("r", circle.radius)
+                .set("fill", rgba_to_hex(circle.color))
+                .set("stroke", "none")
+        })
+        .collect()
+}
+
+/// Number of samples per pixel side used to smooth circle edges in png
+#[cfg(feature = "raster")]
+const SUBSAMPLES: u32 = 4;
+
+/// Function to calculate RGBA pixels of `size` x `size` image,
+/// circles are given for the viewBox centered at zero with `halfsize` half side,
+/// outside of all circles pixels are transparent
+#[cfg(feature = "raster")]
+pub fn calculate_png_data(size: u32, halfsize: f64, circles: &[Circle]) -> Vec<u8> {
+    let scale = 2.0 * halfsize / size as f64;
+    let step = 1.0 / SUBSAMPLES as f64;
+    let samples = SUBSAMPLES * SUBSAMPLES;
+    let mut out: Vec<u8> = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            // sum of premultiplied color components and alpha
+            let mut sum = [0u32; 4];
+            for sub_y in 0..SUBSAMPLES {
+                for sub_x in 0..SUBSAMPLES {
+                    let point_x = (x as f64 + (sub_x as f64 + 0.5) * step) * scale - halfsize;
+                    let point_y = (y as f64 + (sub_y as f64 + 0.5) * step) * scale - halfsize;
+                    // the last drawn circle is on top
+                    let color = circles
+                        .iter()
+                        .rev()
+                        .find(|circle| {
+                            let dx = point_x - circle.x_center;
+                            let dy = point_y - circle.y_center;
+                            dx * dx + dy * dy <= circle.radius * circle.radius
+                        })
+                        .map_or([0u8; 4], |circle| circle.color);
+                    for i in 0..3 {
+                        sum[i] += color[i] as u32 * color[3] as u32;
+                    }
+                    sum[3] += color[3] as u32;
+                }
+            }
+            let alpha = sum[3];
+            for component in sum.iter().take(3) {
+                // fully transparent pixel has no color
+                let value = (component + alpha / 2).checked_div(alpha).unwrap_or(0);
+                out.push(value as u8);
+            }
+            out.push(((alpha + samples / 2) / samples) as u8);
+        }
     }
     out
 }
 
+/// Function to encode RGBA pixels of `size` x `size` image into png file
+#[cfg(feature = "raster")]
+fn encode_png(size: u32, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, size, size);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(data)?;
+    }
+    Ok(out)
+}
+
+/// Function to make png file of `size` x `size` pixels, same picture as `make`
+#[cfg(feature = "raster")]
+pub fn png_from_vec(into_id: &[u8], size: u32, layout: Layout) -> anyhow::Result<Vec<u8>> {
+    let colors = get_colors_from_vec(into_id);
+    let halfsize = HALF_SVG as f64;
+    let circles = calculate_circles(halfsize, colors, layout);
+    encode_png(size, &calculate_png_data(size, halfsize, &circles))
+}
+
+/// Function to make png file of `size` x `size` pixels from svg made by `make`,
+/// so avatars stored on chain can be rasterized without their seed
+#[cfg(feature = "raster")]
+pub fn png_from_svg(svg: &str, size: u32) -> anyhow::Result<Vec<u8>> {
+    let (halfsize, circles) = circles_from_svg(svg)?;
+    encode_png(size, &calculate_png_data(size, halfsize, &circles))
+}
+
+/// Function to read back viewBox half side and circles of svg made by `make`
+#[cfg(feature = "raster")]
+fn circles_from_svg(svg: &str) -> anyhow::Result<(f64, Vec<Circle>)> {
+    fn attribute<'a>(tag: &'a str, name: &str) -> anyhow::Result<&'a str> {
+        let pattern = format!(" {}=\"", name);
+        let start = tag
+            .find(&pattern)
+            .ok_or_else(|| anyhow!("attribute {} not found", name))?
+            + pattern.len();
+        let end = tag[start..]
+            .find('"')
+            .ok_or_else(|| anyhow!("attribute {} not closed", name))?;
+        Ok(&tag[start..start + end])
+    }
+    fn number(tag: &str, name: &str) -> anyhow::Result<f64> {
+        Ok(attribute(tag, name)?.parse()?)
+    }
+
+    let mut tags = svg.split('<').skip(1);
+    let root = tags.next().unwrap_or_default();
+    if !root.starts_with("svg ") {
+        return Err(anyhow!("svg element not found"));
+    }
+    let view_box: Vec<f64> = attribute(root, "viewBox")?
+        .split_whitespace()
+        .map(|value| value.parse())
+        .collect::<Result<_, _>>()?;
+    if view_box.len() != 4 || view_box[0] != -view_box[2] / 2.0 || view_box[2] != view_box[3] {
+        return Err(anyhow!("viewBox must be a square centered at zero"));
+    }
+    let mut circles: Vec<Circle> = Vec::new();
+    for tag in tags {
+        if tag.starts_with("/svg") {
+            break;
+        }
+        if !tag.starts_with("circle ") {
+            return Err(anyhow!("only circles are supported"));
+        }
+        let fill = hex::decode(attribute(tag, "fill")?.trim_start_matches('#'))?;
+        if fill.len() != 3 {
+            return Err(anyhow!("fill must be #rrggbb color"));
+        }
+        circles.push(Circle {
+            x_center: number(tag, "cx")?,
+            y_center: number(tag, "cy")?,
+            radius: number(tag, "r")?,
+            color: [fill[0], fill[1], fill[2], 255],
+        });
+    }
+    Ok((view_box[2] / 2.0, circles))
+}
+
+/// Struct to store coloring scheme chosen for the identicon
+pub struct Scheme {
+    pub name: &'static str,
+    pub rotation: u8,
+    pub colors: Vec<[u8; 4]>,
+}
+
 /// Function to get colors from u8 vector
 pub fn get_colors_from_vec(into_id: &[u8]) -> Vec<[u8; 4]> {
+    scheme_from_vec(into_id).colors
+}
+
+/// Function to get coloring scheme with colors picked from palette from u8 vector
+pub fn scheme_from_vec(into_id: &[u8]) -> Scheme {
     let into_zero = [0u8; 32].to_vec();
     let zero = blake2b(64, &[], &into_zero).as_bytes().to_vec();
 
@@ -324,16 +541,90 @@ pub fn get_colors_from_vec(into_id: &[u8]) -> Vec<[u8; 4]> {
         my_colors.push(color);
     }
 
-    my_colors
+    Scheme {
+        name: my_scheme.name,
+        rotation: rot,
+        colors: my_colors,
+    }
 }
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod unit {
-    use crate::identicon::make;
+    use crate::identicon::{make, make_sized, scheme_from_vec, Layout};
 
     #[test]
     fn test_make() {
-        let actual = make(&[1]);
+        let actual = make(&[1], Layout::Compact);
         assert_eq!("<svg viewBox=\"-32 -32 64 64\" xmlns=\"http://www.w3.org/2000/svg\"><circle cx=\"0\" cy=\"0\" fill=\"#eeeeee\" r=\"32\" stroke=\"none\"/><circle cx=\"-20\" cy=\"-12\" fill=\"#d4aabf\" r=\"5\" stroke=\"none\"/><circle cx=\"-10\" cy=\"-6\" fill=\"#d4caaa\" r=\"5\" stroke=\"none\"/><circle cx=\"-20\" cy=\"0\" fill=\"#3d3c77\" r=\"5\" stroke=\"none\"/><circle cx=\"-20\" cy=\"12\" fill=\"#77623c\" r=\"5\" stroke=\"none\"/><circle cx=\"-10\" cy=\"6\" fill=\"#af7560\" r=\"5\" stroke=\"none\"/><circle cx=\"20\" cy=\"12\" fill=\"#3d3c77\" r=\"5\" stroke=\"none\"/><circle cx=\"10\" cy=\"6\" fill=\"#d4aabf\" r=\"5\" stroke=\"none\"/><circle cx=\"20\" cy=\"0\" fill=\"#d4caaa\" r=\"5\" stroke=\"none\"/><circle cx=\"20\" cy=\"-12\" fill=\"#3d3c77\" r=\"5\" stroke=\"none\"/><circle cx=\"10\" cy=\"-6\" fill=\"#77623c\" r=\"5\" stroke=\"none\"/><circle cx=\"0\" cy=\"0\" fill=\"#af7560\" r=\"5\" stroke=\"none\"/></svg>", actual);
     }
+
+    #[test]
+    fn test_scheme_from_vec() {
+        let scheme = scheme_from_vec(&[1]);
+        assert_eq!("cube", scheme.name);
+        assert_eq!(19, scheme.colors.len());
+    }
+
+    #[test]
+    fn test_make_full() {
+        let actual = make(&[1], Layout::Full);
+        assert_eq!("<svg viewBox=\"-32 -32 64 64\" xmlns=\"http://www.w3.org/2000/svg\"><circle cx=\"0\" cy=\"0\" fill=\"#eeeeee\" r=\"32\" stroke=\"none\"/><circle cx=\"0\" cy=\"-24\" fill=\"#d4aabf\" r=\"5\" stroke=\"none\"/><circle cx=\"0\" cy=\"-12\" fill=\"#d4caaa\" r=\"5\" stroke=\"none\"/><circle cx=\"-10.392304845413264\" cy=\"-18\" fill=\"#3d3c77\" r=\"5\" stroke=\"none\"/><circle cx=\"-20.784609690826528\" cy=\"-12\" fill=\"#77623c\" r=\"5\" stroke=\"none\"/><circle cx=\"-10.392304845413264\" cy=\"-6\" fill=\"#af7560\" r=\"5\" stroke=\"none\"/><circle cx=\"-20.784609690826528\" cy=\"0\" fill=\"#3d3c77\" r=\"5\" stroke=\"none\"/><circle cx=\"-20.784609690826528\" cy=\"12\" fill=\"#d4aabf\" r=\"5\" stroke=\"none\"/><circle cx=\"-10.392304845413264\" cy=\"6\" fill=\"#d4caaa\" r=\"5\" stroke=\"none\"/><circle cx=\"-10.392304845413264\" cy=\"18\" fill=\"#3d3c77\" r=\"5\" stroke=\"none\"/><circle cx=\"0\" cy=\"24\" fill=\"#77623c\" r=\"5\" stroke=\"none\"/><circle cx=\"0\" cy=\"12\" fill=\"#af7560\" r=\"5\" stroke=\"none\"/><circle cx=\"10.392304845413264\" cy=\"18\" fill=\"#3d3c77\" r=\"5\" stroke=\"none\"/><circle cx=\"20.784609690826528\" cy=\"12\" fill=\"#d4aabf\" r=\"5\" stroke=\"none\"/><circle cx=\"10.392304845413264\" cy=\"6\" fill=\"#d4caaa\" r=\"5\" stroke=\"none\"/><circle cx=\"20.784609690826528\" cy=\"0\" fill=\"#3d3c77\" r=\"5\" stroke=\"none\"/><circle cx=\"20.784609690826528\" cy=\"-12\" fill=\"#77623c\" r=\"5\" stroke=\"none\"/><circle cx=\"10.392304845413264\" cy=\"-6\" fill=\"#af7560\" r=\"5\" stroke=\"none\"/><circle cx=\"10.392304845413264\" cy=\"-18\" fill=\"#3d3c77\" r=\"5\" stroke=\"none\"/><circle cx=\"0\" cy=\"0\" fill=\"#5e3c77\" r=\"5\" stroke=\"none\"/></svg>", actual);
+    }
+
+    #[test]
+    fn test_make_full_public_key() {
+        // public key of the `//Alice` development account
+        let public_key =
+            hex::decode("d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d")
+                .unwrap();
+        let actual = make(&public_key, Layout::Full);
+        assert_eq!("<svg viewBox=\"-32 -32 64 64\" xmlns=\"http://www.w3.org/2000/svg\"><circle cx=\"0\" cy=\"0\" fill=\"#eeeeee\" r=\"32\" stroke=\"none\"/><circle cx=\"0\" cy=\"-24\" fill=\"#a5e39c\" r=\"5\" stroke=\"none\"/><circle cx=\"0\" cy=\"-12\" fill=\"#3c2811\" r=\"5\" stroke=\"none\"/><circle cx=\"-10.392304845413264\" cy=\"-18\" fill=\"#b844ca\" r=\"5\" stroke=\"none\"/><circle cx=\"-20.784609690826528\" cy=\"-12\" fill=\"#8b2758\" r=\"5\" stroke=\"none\"/><circle cx=\"-10.392304845413264\" cy=\"-6\" fill=\"#8744ca\" r=\"5\" stroke=\"none\"/><circle cx=\"-20.784609690826528\" cy=\"0\" fill=\"#e19ce3\" r=\"5\" stroke=\"none\"/><circle cx=\"-20.784609690826528\" cy=\"12\" fill=\"#8b2758\" r=\"5\" stroke=\"none\"/><circle cx=\"-10.392304845413264\" cy=\"6\" fill=\"#8744ca\" r=\"5\" stroke=\"none\"/><circle cx=\"-10.392304845413264\" cy=\"18\" fill=\"#b844ca\" r=\"5\" stroke=\"none\"/><circle cx=\"0\" cy=\"24\" fill=\"#a5e39c\" r=\"5\" stroke=\"none\"/><circle cx=\"0\" cy=\"12\" fill=\"#3c2811\" r=\"5\" stroke=\"none\"/><circle cx=\"10.392304845413264\" cy=\"18\" fill=\"#a2ca44\" r=\"5\" stroke=\"none\"/><circle cx=\"20.784609690826528\" cy=\"12\" fill=\"#278b8b\" r=\"5\" stroke=\"none\"/><circle cx=\"10.392304845413264\" cy=\"6\" fill=\"#bbca44\" r=\"5\" stroke=\"none\"/><circle cx=\"20.784609690826528\" cy=\"0\" fill=\"#263c11\" r=\"5\" stroke=\"none\"/><circle cx=\"20.784609690826528\" cy=\"-12\" fill=\"#278b8b\" r=\"5\" stroke=\"none\"/><circle cx=\"10.392304845413264\" cy=\"-6\" fill=\"#bbca44\" r=\"5\" stroke=\"none\"/><circle cx=\"10.392304845413264\" cy=\"-18\" fill=\"#a2ca44\" r=\"5\" stroke=\"none\"/><circle cx=\"0\" cy=\"0\" fill=\"#3d278b\" r=\"5\" stroke=\"none\"/></svg>", actual);
+    }
+
+    #[test]
+    fn test_make_sized() {
+        assert_eq!(
+            make(&[1], Layout::Full).replace("<svg ", "<svg height=\"64\" "),
+            make_sized(&[1], Layout::Full, 64).replace(" width=\"64\"", "")
+        );
+        assert_eq!("<svg height=\"16\" viewBox=\"-8 -8 16 16\" width=\"16\" xmlns=\"http://www.w3.org/2000/svg\"><circle cx=\"0\" cy=\"0\" fill=\"#eeeeee\" r=\"8\" stroke=\"none\"/><circle cx=\"-5\" cy=\"-3\" fill=\"#d4aabf\" r=\"1.25\" stroke=\"none\"/><circle cx=\"-2.5\" cy=\"-1.5\" fill=\"#d4caaa\" r=\"1.25\" stroke=\"none\"/><circle cx=\"-5\" cy=\"0\" fill=\"#3d3c77\" r=\"1.25\" stroke=\"none\"/><circle cx=\"-5\" cy=\"3\" fill=\"#77623c\" r=\"1.25\" stroke=\"none\"/><circle cx=\"-2.5\" cy=\"1.5\" fill=\"#af7560\" r=\"1.25\" stroke=\"none\"/><circle cx=\"5\" cy=\"3\" fill=\"#3d3c77\" r=\"1.25\" stroke=\"none\"/><circle cx=\"2.5\" cy=\"1.5\" fill=\"#d4aabf\" r=\"1.25\" stroke=\"none\"/><circle cx=\"5\" cy=\"0\" fill=\"#d4caaa\" r=\"1.25\" stroke=\"none\"/><circle cx=\"5\" cy=\"-3\" fill=\"#3d3c77\" r=\"1.25\" stroke=\"none\"/><circle cx=\"2.5\" cy=\"-1.5\" fill=\"#77623c\" r=\"1.25\" stroke=\"none\"/><circle cx=\"0\" cy=\"0\" fill=\"#af7560\" r=\"1.25\" stroke=\"none\"/></svg>", make_sized(&[1], Layout::Compact, 16));
+    }
+
+    #[cfg(feature = "raster")]
+    #[test]
+    fn test_png_from_vec() {
+        use crate::identicon::{
+            calculate_circles, calculate_png_data, get_colors_from_vec, png_from_vec,
+        };
+
+        let circles = calculate_circles(32.0, get_colors_from_vec(&[1]), Layout::Compact);
+        let data = calculate_png_data(64, 32.0, &circles);
+        assert_eq!(64 * 64 * 4, data.len());
+        let pixel = |x: usize, y: usize| data[(y * 64 + x) * 4..(y * 64 + x) * 4 + 4].to_vec();
+        // corner is outside of the background circle
+        assert_eq!(vec![0, 0, 0, 0], pixel(0, 0));
+        // background between circles
+        assert_eq!(vec![238, 238, 238, 255], pixel(32, 8));
+        // center circle, same color as in svg
+        assert_eq!(vec![0xaf, 0x75, 0x60, 255], pixel(32, 32));
+
+        let png = png_from_vec(&[1], 16, Layout::Full).unwrap();
+        assert_eq!(&[0x89, b'P', b'N', b'G'], &png[0..4]);
+        assert_eq!(png, png_from_vec(&[1], 16, Layout::Full).unwrap());
+        assert_ne!(png, png_from_vec(&[1], 17, Layout::Full).unwrap());
+    }
+
+    #[cfg(feature = "raster")]
+    #[test]
+    fn test_png_from_svg() {
+        use crate::identicon::{png_from_svg, png_from_vec};
+
+        for layout in [Layout::Compact, Layout::Full].iter() {
+            assert_eq!(
+                png_from_vec(&[1], 48, *layout).unwrap(),
+                png_from_svg(&make(&[1], *layout), 48).unwrap()
+            );
+        }
+        assert!(png_from_svg("<svg viewBox=\"0 0 1 1\"><rect/></svg>", 48).is_err());
+    }
 }
@@ -0,0 +1,91 @@
+//! Image media types of avatars and their detection by content.
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// Type of the avatar image, the subtype of `image/*`
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum MediaType {
+    Svg,
+    Png,
+    Webp,
+    Gif,
+    Jpeg,
+}
+
+impl Default for MediaType {
+    /// Identicons and the logo are svg
+    fn default() -> Self {
+        MediaType::Svg
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG_SIGNATURE: &[u8] = b"\xff\xd8\xff";
+const GIF_SIGNATURES: [&[u8]; 2] = [b"GIF87a", b"GIF89a"];
+
+impl MediaType {
+    /// Subtype as used in `data:image/{subtype};base64,` URIs
+    pub fn subtype(self) -> &'static str {
+        match self {
+            MediaType::Svg => "svg+xml",
+            MediaType::Png => "png",
+            MediaType::Webp => "webp",
+            MediaType::Gif => "gif",
+            MediaType::Jpeg => "jpeg",
+        }
+    }
+
+    /// Detects the type by the magic bytes, svg by its root element
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        if data.starts_with(PNG_SIGNATURE) {
+            Some(MediaType::Png)
+        } else if data.starts_with(JPEG_SIGNATURE) {
+            Some(MediaType::Jpeg)
+        } else if GIF_SIGNATURES
+            .iter()
+            .any(|signature| data.starts_with(signature))
+        {
+            Some(MediaType::Gif)
+        } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+            Some(MediaType::Webp)
+        } else if std::str::from_utf8(data).map_or(false, is_svg) {
+            Some(MediaType::Svg)
+        } else {
+            None
+        }
+    }
+}
+
+fn is_svg(text: &str) -> bool {
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    (text.starts_with("<svg") || text.starts_with("<?xml") || text.starts_with("<!--"))
+        && text.contains("<svg")
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod unit {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(
+            Some(MediaType::Png),
+            MediaType::sniff(b"\x89PNG\r\n\x1a\n\0\0")
+        );
+        assert_eq!(Some(MediaType::Jpeg), MediaType::sniff(b"\xff\xd8\xff\xe0"));
+        assert_eq!(Some(MediaType::Gif), MediaType::sniff(b"GIF89a\x01\0"));
+        assert_eq!(
+            Some(MediaType::Webp),
+            MediaType::sniff(b"RIFF\0\0\0\0WEBPVP8 ")
+        );
+        assert_eq!(
+            Some(MediaType::Svg),
+            MediaType::sniff(b"<?xml version=\"1.0\"?><svg/>")
+        );
+        assert_eq!(None, MediaType::sniff(b"RIFF\0\0\0\0WAVE"));
+        assert_eq!(None, MediaType::sniff(b"<html></html>"));
+    }
+}
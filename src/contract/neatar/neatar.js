@@ -13,34 +13,118 @@ export class Neatar {
     this.contract = new Contract(account, contractId, {
       viewMethods: [
         'avatar_of',
+        'avatars_of',
+        'avatar_hash_of',
+        'avatar_token_of',
+        'state_version',
+        'admin_owner',
+        'admin_roles_of',
+        'admin_operators',
+        'avatar_of_size',
         'avatar_exist',
+        'avatar_id_of',
+        'avatar_issued_at',
+        'avatar_edition',
+        'avatar_by_edition',
+        'avatar_minted',
+        'avatar_media_type',
+        'avatar_royalty',
+        'avatar_royalty_of',
+        'avatar_soulbound',
+        'nft_payout',
+        'identicon_of',
         'nft_tokens_for_owner',
         'nft_metadata',
+        'storage_balance_of',
+        'storage_balance_bounds',
       ],
       changeMethods: [
         'new',
         'avatar_create',
         'avatar_create_for',
+        'avatar_upload',
+        'avatar_upload_ipfs',
+        'avatar_upload_image',
+        'avatar_commit',
+        'avatar_reveal',
         'avatar_burn_for',
         'avatar_burn',
+        'avatar_regenerate',
+        'avatar_set_active',
         'ft_burn',
+        'nft_transfer_payout',
+        'storage_deposit',
+        'storage_withdraw',
+        'storage_unregister',
+        'admin_grant_role',
+        'admin_revoke_role',
+        'admin_transfer_ownership',
       ],
     })
   }
 
   /**
    * @param {string} account_id
+   * @param {'logo'|'identicon'|null} fallback
    * @returns {Promise<string>}
    */
-  avatar_of(account_id) {
-    return this.contract.avatar_of({account_id})
+  avatar_of(account_id, fallback = null) {
+    return this.contract.avatar_of({account_id, fallback})
   }
 
   /**
+   * At most 50 accounts per call
+   * @param {string[]} account_ids
+   * @param {'logo'|'identicon'|null} fallback
+   * @returns {Promise<{account_id: string, avatar: string, exist: boolean}[]>}
+   */
+  avatars_of(account_ids, fallback = null) {
+    return this.contract.avatars_of({account_ids, fallback})
+  }
+
+  /**
+   * Base64 sha256 of the avatar media, null for a fallback
+   * @param {string} account_id
+   * @returns {Promise<string|null>}
+   */
+  avatar_hash_of(account_id) {
+    return this.contract.avatar_hash_of({account_id})
+  }
+
+  /**
+   * Avatar token without media
+   * @param {string} account_id
+   * @returns {Promise<Object|null>}
+   */
+  avatar_token_of(account_id) {
+    return this.contract.avatar_token_of({account_id})
+  }
+
+  /**
+   * @param {string} account_id
+   * @param {number} size
+   * @param {'logo'|'identicon'|null} fallback
    * @returns {Promise<string>}
    */
-  avatar_create() {
-    return this.contract.avatar_create({}, AVATAR_CREATE_PREPAID_GAS, AVATAR_CREATE_STORAGE_COST)
+  avatar_of_size(account_id, size, fallback = null) {
+    return this.contract.avatar_of_size({account_id, size, fallback})
+  }
+
+  /**
+   * @param {string} account_id
+   * @returns {Promise<string>}
+   */
+  identicon_of(account_id) {
+    return this.contract.identicon_of({account_id})
+  }
+
+  /**
+   * @param {'random'|'identity'|null} entropy
+   * @param {boolean|null} soulbound
+   * @returns {Promise<string>}
+   */
+  avatar_create(entropy = null, soulbound = null) {
+    return this.contract.avatar_create({entropy, soulbound}, AVATAR_CREATE_PREPAID_GAS, AVATAR_CREATE_STORAGE_COST)
   }
 
   /**
@@ -58,6 +142,14 @@ export class Neatar {
     return this.contract.avatar_burn({}, AVATAR_CREATE_PREPAID_GAS)
   }
 
+  /**
+   * @param {string} token_id
+   * @returns {Promise<void>}
+   */
+  avatar_set_active(token_id) {
+    return this.contract.avatar_set_active({token_id})
+  }
+
   /**
    * @returns {Promise<Object>}
    */
@@ -73,6 +165,22 @@ export class Neatar {
     return this.contract.avatar_exist({account_id})
   }
 
+  /**
+   * @param {string} account_id
+   * @returns {Promise<string|null>}
+   */
+  avatar_id_of(account_id) {
+    return this.contract.avatar_id_of({account_id})
+  }
+
+  /**
+   * @param {string} account_id
+   * @returns {Promise<string|null>}
+   */
+  avatar_issued_at(account_id) {
+    return this.contract.avatar_issued_at({account_id})
+  }
+
   /**
    * @param {string} account_id
    * @param {string} from_index
@@ -1,23 +1,40 @@
 use cid::multihash::{Code, MultihashDigest};
 use cid::Cid;
+use near_contract_standards::non_fungible_token::approval::{
+    ext_approval_receiver, NonFungibleTokenApproval,
+};
+use near_contract_standards::non_fungible_token::core::{
+    NonFungibleTokenCore, NonFungibleTokenResolver,
+};
 use near_contract_standards::non_fungible_token::metadata::{
     NFTContractMetadata, NonFungibleTokenMetadataProvider, TokenMetadata, NFT_METADATA_SPEC,
 };
 use near_contract_standards::non_fungible_token::NonFungibleToken;
 use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LazyOption;
+use near_sdk::collections::{LazyOption, LookupMap, UnorderedMap, UnorderedSet};
 use near_sdk::json_types::Base64VecU8;
 use near_sdk::log;
 use near_sdk::require;
-use near_sdk::serde_json::json;
-use near_sdk::Balance;
-use near_sdk::Gas;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json;
+use near_sdk::{assert_one_yocto, Balance, Gas, StorageUsage};
 use near_sdk::{
     env, near_bindgen, AccountId, BorshStorageKey, PanicOnDefault, Promise, PromiseOrValue,
 };
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+mod event;
+pub mod identicon;
+pub mod media;
+mod sanitize;
 
-mod identicon;
+use identicon::Layout;
+use media::MediaType;
 
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 lazy_static_include::lazy_static_include_str! {
@@ -31,7 +48,7 @@ fn new_nft_metadata() -> NFTContractMetadata {
         symbol: PKG_NAME.to_uppercase(),
         icon: Some(format!(
             "data:image/{}",
-            pack_data_image(LOGO.to_string(), None)
+            pack_data_image(LOGO.as_bytes(), MediaType::Svg)
         )),
         base_uri: Some("data:image".to_string()),
         reference: None,
@@ -41,45 +58,246 @@ fn new_nft_metadata() -> NFTContractMetadata {
     metadata
 }
 
-fn pack_data_image(data: String, media_type: Option<String>) -> String {
+/// Media relative to the `data:image` base URI of the contract.
+fn pack_data_image(data: &[u8], media_type: MediaType) -> String {
+    format!("{};base64,{}", media_type.subtype(), base64::encode(data))
+}
+
+/// Inverse of `pack_data_image`, returns the media type and decoded data.
+pub fn unpack_data_image(media: &str) -> Option<(String, Vec<u8>)> {
+    let (media_type, data) = media.split_once(";base64,")?;
+    Some((media_type.to_string(), base64::decode(data).ok()?))
+}
+
+/// Position of the `name="` attribute among the `attributes` of a tag.
+fn find_attribute(attributes: &str, name: &str) -> Option<usize> {
+    attributes
+        .match_indices(&format!("{}=\"", name))
+        .map(|(i, _)| i)
+        .find(|&i| attributes[..i].ends_with(char::is_whitespace))
+}
+
+/// Sets `width` and `height` of the root `svg` element, the `viewBox` keeps the picture proportional.
+/// An svg sized only by `width` and `height` gets a `viewBox` of that size, so it scales instead of cropping.
+fn resize_svg(svg: &str, size: u32) -> String {
+    let start = match svg.find("<svg") {
+        Some(start) => start + "<svg".len(),
+        None => return svg.to_string(),
+    };
+    let end = svg[start..].find('>').map_or(svg.len(), |end| start + end);
+    let mut attributes = svg[start..end].to_string();
+    let mut original = Vec::new();
+    for name in ["width", "height"].iter() {
+        if let Some(i) = find_attribute(&attributes, name) {
+            let value_start = i + name.len() + "=\"".len();
+            let value_end = attributes[value_start..]
+                .find('"')
+                .map_or(attributes.len(), |j| value_start + j);
+            let value = attributes[value_start..value_end].trim_end_matches("px");
+            if value.parse::<f64>().map_or(false, |value| value > 0.0) {
+                original.push(value.to_string());
+            }
+            attributes.replace_range(i..(value_end + 1).min(attributes.len()), "");
+        }
+    }
+    let view_box = match original.as_slice() {
+        [width, height] if find_attribute(&attributes, "viewBox").is_none() => {
+            format!(" viewBox=\"0 0 {} {}\"", width, height)
+        }
+        _ => String::new(),
+    };
     format!(
-        "{};base64,{}",
-        media_type.unwrap_or_else(|| "svg+xml".to_string()),
-        base64::encode(data)
+        "{}<svg width=\"{}\" height=\"{}\"{}{}{}",
+        &svg[..start - "<svg".len()],
+        size,
+        size,
+        view_box,
+        attributes,
+        &svg[end..]
     )
 }
 
 fn default_token() -> Token {
-    new_token(LOGO.to_string(), None)
+    new_token(LOGO.to_string(), None, TITLE_TEMPLATE, None)
+}
+
+/// CIDv1 of raw sha2-256 multihash of the data, the way IPFS addresses it.
+pub fn token_id_of(data: &[u8]) -> TokenId {
+    let hash = Code::Sha2_256.digest(data);
+    Cid::new_v1(RAW, hash).to_string()
+}
+
+/// Takes the cost of `storage_used` bytes from the attached deposit
+/// and refunds the remainder to the payer.
+fn refund_deposit(storage_used: u64) {
+    let required_cost = env::storage_byte_cost() * Balance::from(storage_used);
+    let attached_deposit = env::attached_deposit();
+    require!(
+        required_cost <= attached_deposit,
+        format!(
+            "Must attach {} yoctoNEAR to cover storage, attached {}",
+            required_cost, attached_deposit
+        )
+    );
+    let refund = attached_deposit - required_cost;
+    if refund > 1 {
+        Promise::new(env::predecessor_account_id()).transfer(refund);
+    }
 }
 
-fn new_token(svg: String, owner_id: Option<AccountId>) -> Token {
-    let hash = Code::Sha2_256.digest(svg.as_bytes());
-    let token_id = Cid::new_v1(RAW, hash).to_string();
+fn new_token(
+    svg: String,
+    owner_id: Option<AccountId>,
+    title_template: &str,
+    edition: Option<u64>,
+) -> Token {
+    let token_id = token_id_of(svg.as_bytes());
+    let media_hash = env::sha256(svg.as_bytes());
+    let media = pack_data_image(svg.as_bytes(), MediaType::Svg);
     let owner_id = owner_id.unwrap_or_else(env::current_account_id);
+    let title = token_title(title_template, &token_id, edition);
+    let extra = edition.map(|edition| TokenExtra {
+        edition,
+        media_type: MediaType::Svg,
+        soulbound: false,
+    });
     Token {
-        token_id: token_id.clone(),
+        token_id,
         owner_id,
-        metadata: Some(new_token_metadata(svg, token_id)),
+        metadata: Some(new_token_metadata(media, media_hash, title, extra)),
         approved_account_ids: None,
     }
 }
 
-fn new_token_metadata(svg: String, token_id: String) -> TokenMetadata {
-    let title = Some(format!(
-        "#{}",
-        if token_id.len() > 6 {
-            format!(
-                "{}...{}",
-                &token_id[0..3],
-                &token_id[token_id.len() - 4..token_id.len() - 1]
-            )
-        } else {
-            token_id
-        },
-    ));
-    let media = pack_data_image(svg.clone(), None);
-    let media_hash = Base64VecU8(env::sha256(svg.as_bytes()));
+/// Checks that `cid` addresses the raw content with sha256 `media_hash`,
+/// the way `token_id_of` does, and returns it in the same form.
+fn ipfs_token_id(cid: &str, media_hash: &[u8]) -> TokenId {
+    let cid = Cid::try_from(cid).expect("Invalid CID");
+    require!(
+        cid.codec() == RAW && cid.hash().code() == u64::from(Code::Sha2_256),
+        "CID must be CIDv1 of raw sha2-256"
+    );
+    require!(
+        cid.hash().digest() == media_hash,
+        "Media hash does not match CID"
+    );
+    Cid::new_v1(RAW, *cid.hash()).to_string()
+}
+
+/// The part of the token id after the prefix shared by all tokens,
+/// shortened to its first and last characters.
+fn token_short_id(token_id: &str) -> String {
+    let unique = token_id.strip_prefix(TOKEN_ID_PREFIX).unwrap_or(token_id);
+    if unique.len() > 2 * TOKEN_SHORT_ID_PART + 3 {
+        format!(
+            "{}...{}",
+            &unique[..TOKEN_SHORT_ID_PART],
+            &unique[unique.len() - TOKEN_SHORT_ID_PART..]
+        )
+    } else {
+        unique.to_string()
+    }
+}
+
+/// Title of the token from a template, `{id}` stands for the short token id
+/// and `{edition}` for the edition number.
+fn token_title(template: &str, token_id: &str, edition: Option<u64>) -> String {
+    let edition = edition
+        .map(|edition| edition.to_string())
+        .unwrap_or_default();
+    template
+        .replace("{id}", &token_short_id(token_id))
+        .replace("{edition}", &edition)
+}
+
+/// Extra token metadata, stored as JSON in `TokenMetadata::extra`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct TokenExtra {
+    /// Position of the token in the mint order, starting from 1.
+    edition: u64,
+    /// Tokens minted before media types were recorded are svg.
+    #[serde(default)]
+    media_type: MediaType,
+    /// The token can't be transferred or approved for transfer.
+    #[serde(default)]
+    soulbound: bool,
+}
+
+/// Image of a new token, `media` goes to `TokenMetadata::media` as is.
+struct NewMedia {
+    media: String,
+    media_hash: Vec<u8>,
+    media_type: MediaType,
+}
+
+/// How a token is minted besides its media.
+struct MintOptions<'a> {
+    /// Uploader of the art, gets the creator share of royalties.
+    creator_id: Option<&'a AccountId>,
+    soulbound: bool,
+}
+
+fn token_extra(metadata: &TokenMetadata) -> Option<TokenExtra> {
+    serde_json::from_str(metadata.extra.as_ref()?).ok()
+}
+
+fn token_edition(metadata: &TokenMetadata) -> Option<u64> {
+    token_extra(metadata).map(|extra| extra.edition)
+}
+
+/// Media type recorded on the token, tokens without it are svg.
+fn token_media_type(metadata: &TokenMetadata) -> MediaType {
+    token_extra(metadata)
+        .map(|extra| extra.media_type)
+        .unwrap_or_default()
+}
+
+/// Numbers the tokens minted before editions in `issued_at` order,
+/// returns the number of tokens, so the next mint takes the following edition.
+fn backfill_editions(token: &mut NonFungibleToken, editions: &mut LookupMap<u64, TokenId>) -> u64 {
+    let by_id = token
+        .token_metadata_by_id
+        .as_mut()
+        .expect("Token metadata is not supported");
+    let mut issued: Vec<(u64, TokenId)> = token
+        .owner_by_id
+        .iter()
+        .map(|(token_id, _)| {
+            let issued_at = by_id
+                .get(&token_id)
+                .and_then(|metadata| metadata.issued_at)
+                .and_then(|issued_at| issued_at.parse().ok())
+                .unwrap_or_default();
+            (issued_at, token_id)
+        })
+        .collect();
+    issued.sort();
+    for (index, (_, token_id)) in issued.iter().enumerate() {
+        let edition = index as u64 + 1;
+        if let Some(mut metadata) = by_id.get(token_id) {
+            let extra = TokenExtra {
+                edition,
+                media_type: MediaType::Svg,
+                soulbound: false,
+            };
+            metadata.extra = Some(serde_json::to_string(&extra).unwrap());
+            by_id.insert(token_id, &metadata);
+        }
+        editions.insert(&edition, token_id);
+    }
+    issued.len() as u64
+}
+
+fn new_token_metadata(
+    media: String,
+    media_hash: Vec<u8>,
+    title: String,
+    extra: Option<TokenExtra>,
+) -> TokenMetadata {
+    let extra = extra.map(|extra| serde_json::to_string(&extra).unwrap());
+    let title = Some(title);
+    let media_hash = Base64VecU8(media_hash);
     TokenMetadata {
         title,
         description: None,
@@ -90,24 +308,329 @@ fn new_token_metadata(svg: String, token_id: String) -> TokenMetadata {
         expires_at: None,
         starts_at: None,
         updated_at: None,
-        extra: None,
+        extra,
         reference: None,
         reference_hash: None,
     }
 }
 
-// Prepaid gas for making a single simple call.
-const SINGLE_CALL_GAS: Gas = Gas(200000000000000);
-const ONE_YOCTO: Balance = 1;
+/// Source of randomness mixed with the owner id to generate an identicon.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum Entropy {
+    /// VRF backed `env::random_seed()` of the block.
+    Random,
+    /// Block timestamp, predictable and kept for tests and reproducible mints.
+    Timestamp,
+    /// Account id alone, the same picture for the account every time.
+    Identity,
+}
+
+impl Entropy {
+    fn seed(&self, owner_id: &AccountId) -> Vec<u8> {
+        match self {
+            Entropy::Random => env::sha256(&[owner_id.as_bytes(), &env::random_seed()].concat()),
+            Entropy::Timestamp => {
+                env::sha256(format!("{}-{}", owner_id, env::block_timestamp()).as_bytes())
+            }
+            Entropy::Identity => owner_id.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Entropy accepted from users, the predictable timestamp is left to the contract.
+fn user_entropy(entropy: Option<Entropy>) -> Entropy {
+    let entropy = entropy.unwrap_or(Entropy::Random);
+    require!(
+        entropy != Entropy::Timestamp,
+        "Timestamp entropy is available only via avatar_create_for"
+    );
+    entropy
+}
+
+/// What `avatar_of` returns for an account without a minted avatar.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum Fallback {
+    /// The shared contract logo.
+    Logo,
+    /// Identicon seeded by the account id, rendered on the fly.
+    Identicon,
+}
+
+/// Roles the owner grants to operators, the owner acts in all of them.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum Role {
+    /// Mints avatars to other accounts with `avatar_create_for`.
+    Minter,
+    /// Burns avatars of other accounts with `avatar_burn_for`.
+    Moderator,
+    /// Runs `migrate` and `migrate_titles` after a deploy.
+    Upgrader,
+}
+
+/// Account with the roles granted to it, as listed by `admin_operators`.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Operator {
+    pub account_id: AccountId,
+    pub roles: Vec<Role>,
+}
+
+/// Royalty split of secondary sales in basis points, the creator
+/// share applies to uploaded art only.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Royalty {
+    pub treasury_id: AccountId,
+    pub treasury: u32,
+    pub creator: u32,
+}
+
+impl Royalty {
+    /// Receivers of a new token, those with a zero share are left out.
+    fn split(&self, creator_id: Option<&AccountId>) -> HashMap<AccountId, u32> {
+        let mut split = HashMap::new();
+        if self.treasury > 0 {
+            split.insert(self.treasury_id.clone(), self.treasury);
+        }
+        if let Some(creator_id) = creator_id.filter(|_| self.creator > 0) {
+            *split.entry(creator_id.clone()).or_insert(0) += self.creator;
+        }
+        split
+    }
+}
+
+/// Amounts each receiver gets from a sale, NEP-199.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Payout {
+    pub payout: HashMap<AccountId, U128>,
+}
+
+/// Avatar of an account as returned by `avatars_of`.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountAvatar {
+    pub account_id: AccountId,
+    /// Data URI or gateway URL, the same as `avatar_of` returns.
+    pub avatar: String,
+    /// Whether the avatar is a minted token rather than a fallback.
+    pub exist: bool,
+}
+
+/// Hash of a secret committed by the owner before `avatar_reveal`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Commitment {
+    hash: Vec<u8>,
+    block_height: u64,
+}
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Neatar {
     token: NonFungibleToken,
     metadata: LazyOption<NFTContractMetadata>,
+    /// Avatar chosen by the owner among the tokens they hold.
+    active_tokens: LookupMap<AccountId, TokenId>,
+    commitments: LookupMap<AccountId, Commitment>,
+    fallback: Fallback,
+    /// Identicon layout of new avatars and fallbacks.
+    layout: Layout,
+    /// Prepaid storage (NEP-145) left on each registered account.
+    storage_deposits: LookupMap<AccountId, Balance>,
+    /// Bytes taken by the registration of the longest account id.
+    account_storage_usage: StorageUsage,
+    /// Template of the titles of new avatars, see `token_title`.
+    title_template: String,
+    /// Number of avatars minted so far, the edition of the last one.
+    minted: u64,
+    editions: LookupMap<u64, TokenId>,
+    /// Prefix of the URLs `avatar_of` returns for media on IPFS.
+    ipfs_gateway: String,
+    /// Split recorded on new tokens.
+    royalty: Royalty,
+    /// Royalty receivers of each token in basis points, see `nft_payout`.
+    royalties: LookupMap<TokenId, HashMap<AccountId, u32>>,
+    /// Every new token is soulbound regardless of what the minter asks for.
+    soulbound: bool,
+    /// Account in charge of the settings and the roles, see `assert_owner`.
+    owner_id: AccountId,
+    operators: UnorderedMap<AccountId, Vec<Role>>,
+}
+
+/// Layout of the state before active avatars were introduced.
+#[derive(BorshDeserialize)]
+struct NeatarV1 {
+    token: NonFungibleToken,
+    metadata: LazyOption<NFTContractMetadata>,
+}
+
+/// Layout of the state before the owner and operator roles were introduced.
+#[derive(BorshDeserialize)]
+#[cfg_attr(all(test, not(target_arch = "wasm32")), derive(BorshSerialize))]
+struct NeatarV2 {
+    token: NonFungibleToken,
+    metadata: LazyOption<NFTContractMetadata>,
+    active_tokens: LookupMap<AccountId, TokenId>,
+    commitments: LookupMap<AccountId, Commitment>,
+    fallback: Fallback,
+    layout: Layout,
+    storage_deposits: LookupMap<AccountId, Balance>,
+    account_storage_usage: StorageUsage,
+    title_template: String,
+    minted: u64,
+    editions: LookupMap<u64, TokenId>,
+    ipfs_gateway: String,
+    royalty: Royalty,
+    royalties: LookupMap<TokenId, HashMap<AccountId, u32>>,
+    soulbound: bool,
+}
+
+/// Bytes taken by the registration of the longest account id.
+fn measure_account_storage_usage(
+    storage_deposits: &mut LookupMap<AccountId, Balance>,
+) -> StorageUsage {
+    let initial_storage_usage = env::storage_usage();
+    let tmp_account_id = AccountId::new_unchecked("a".repeat(64));
+    storage_deposits.insert(&tmp_account_id, &0);
+    let storage_usage = env::storage_usage() - initial_storage_usage;
+    storage_deposits.remove(&tmp_account_id);
+    storage_usage
+}
+
+/// None of the later fields exist yet, they start with the defaults of `Neatar::new`
+/// except the editions, numbered in the mint order of the existing tokens.
+impl From<NeatarV1> for NeatarV2 {
+    fn from(v1: NeatarV1) -> Self {
+        let mut token = v1.token;
+        let mut editions = LookupMap::new(StorageKey::Edition);
+        let minted = backfill_editions(&mut token, &mut editions);
+        let mut storage_deposits = LookupMap::new(StorageKey::StorageDeposit);
+        let account_storage_usage = measure_account_storage_usage(&mut storage_deposits);
+        Self {
+            token,
+            metadata: v1.metadata,
+            active_tokens: LookupMap::new(StorageKey::ActiveToken),
+            commitments: LookupMap::new(StorageKey::Commitment),
+            fallback: Fallback::Identicon,
+            layout: Layout::Compact,
+            storage_deposits,
+            account_storage_usage,
+            title_template: TITLE_TEMPLATE.to_string(),
+            minted,
+            editions,
+            ipfs_gateway: IPFS_GATEWAY.to_string(),
+            royalty: Royalty {
+                treasury_id: env::current_account_id(),
+                treasury: 0,
+                creator: 0,
+            },
+            royalties: LookupMap::new(StorageKey::Royalty),
+            soulbound: false,
+        }
+    }
+}
+
+/// The contract account stays the owner until it transfers the ownership.
+impl From<NeatarV2> for Neatar {
+    fn from(v2: NeatarV2) -> Self {
+        Self {
+            token: v2.token,
+            metadata: v2.metadata,
+            active_tokens: v2.active_tokens,
+            commitments: v2.commitments,
+            fallback: v2.fallback,
+            layout: v2.layout,
+            storage_deposits: v2.storage_deposits,
+            account_storage_usage: v2.account_storage_usage,
+            title_template: v2.title_template,
+            minted: v2.minted,
+            editions: v2.editions,
+            ipfs_gateway: v2.ipfs_gateway,
+            royalty: v2.royalty,
+            royalties: v2.royalties,
+            soulbound: v2.soulbound,
+            owner_id: env::current_account_id(),
+            operators: UnorderedMap::new(StorageKey::Operator),
+        }
+    }
+}
+
+/// Every layout the state has had, each variant upgrades to the next one.
+enum VersionedNeatar {
+    V1(NeatarV1),
+    V2(NeatarV2),
+    V3(Neatar),
+}
+
+impl VersionedNeatar {
+    fn read() -> Self {
+        let state = env::storage_read(STATE_KEY).expect("State doesn't exist");
+        match read_state_version(&state) {
+            1 => Self::V1(NeatarV1::try_from_slice(&state).expect("Cannot read state V1")),
+            2 => Self::V2(NeatarV2::try_from_slice(&state).expect("Cannot read state V2")),
+            STATE_VERSION => Self::V3(Neatar::try_from_slice(&state).expect("Cannot read state")),
+            version => env::panic_str(&format!("Unknown state version {}", version)),
+        }
+    }
+
+    fn upgrade(self) -> Neatar {
+        let mut state = self;
+        loop {
+            state = match state {
+                Self::V1(v1) => Self::V2(v1.into()),
+                Self::V2(v2) => Self::V3(v2.into()),
+                Self::V3(current) => return current,
+            }
+        }
+    }
+}
+
+/// Version stored along the state, or guessed by the layout for states
+/// written before it was stored.
+fn read_state_version(state: &[u8]) -> u32 {
+    match env::storage_read(STATE_VERSION_KEY) {
+        Some(version) => u32::try_from_slice(&version).expect("Cannot read state version"),
+        None if NeatarV1::try_from_slice(state).is_ok() => 1,
+        None => 2,
+    }
 }
 
+fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &STATE_VERSION.try_to_vec().unwrap());
+}
+
+const STATE_KEY: &[u8] = b"STATE";
+const STATE_VERSION_KEY: &[u8] = b"VERSION";
+/// Version of the `Neatar` layout, bump it with a new `VersionedNeatar` variant.
+const STATE_VERSION: u32 = 3;
+
 const RAW: u64 = 0x55;
+/// Largest side in pixels accepted by `avatar_of_size`.
+const AVATAR_SIZE_MAX: u32 = 2048;
+/// Most accounts `avatars_of` takes at once, a page of a feed, kept low
+/// since every identicon fallback is rendered within the view gas limit.
+const AVATARS_OF_MAX: usize = 50;
+/// Largest svg in bytes accepted by `avatar_upload`.
+const AVATAR_UPLOAD_MAX: usize = 16 * 1024;
+/// Multibase, version, codec and multihash prefix common to all token ids.
+const TOKEN_ID_PREFIX: &str = "bafkrei";
+/// Characters kept from each end of the unique part of the token id in titles.
+const TOKEN_SHORT_ID_PART: usize = 4;
+const TITLE_TEMPLATE: &str = "#{id}";
+const IPFS_SCHEME: &str = "ipfs://";
+const IPFS_GATEWAY: &str = "https://ipfs.io/ipfs/";
+/// Basis points of a whole sale.
+const ROYALTY_BASE: u32 = 10_000;
+/// Largest total royalty, the owner keeps the rest.
+const ROYALTY_MAX: u32 = 5_000;
+const GAS_FOR_NFT_APPROVE: Gas = Gas(10_000_000_000_000);
 
 #[derive(BorshSerialize, BorshStorageKey)]
 enum StorageKey {
@@ -116,6 +639,13 @@ enum StorageKey {
     TokenMetadata,
     Enumeration,
     Approval,
+    ActiveToken,
+    Commitment,
+    TokensPerOwner { account_hash: Vec<u8> },
+    StorageDeposit,
+    Edition,
+    Royalty,
+    Operator,
 }
 
 #[near_bindgen]
@@ -129,132 +659,1136 @@ impl Neatar {
 
     fn new() -> Self {
         let owner_id = env::current_account_id();
-        Self {
+        let mut storage_deposits = LookupMap::new(StorageKey::StorageDeposit);
+        let account_storage_usage = measure_account_storage_usage(&mut storage_deposits);
+        let this = Self {
             token: NonFungibleToken::new(
                 StorageKey::NonFungibleToken,
-                owner_id,
+                owner_id.clone(),
                 Some(StorageKey::TokenMetadata),
                 Some(StorageKey::Enumeration),
                 Some(StorageKey::Approval),
             ),
             metadata: LazyOption::new(StorageKey::Metadata, Some(&new_nft_metadata())),
+            active_tokens: LookupMap::new(StorageKey::ActiveToken),
+            commitments: LookupMap::new(StorageKey::Commitment),
+            fallback: Fallback::Identicon,
+            layout: Layout::Compact,
+            storage_deposits,
+            account_storage_usage,
+            title_template: TITLE_TEMPLATE.to_string(),
+            minted: 0,
+            editions: LookupMap::new(StorageKey::Edition),
+            ipfs_gateway: IPFS_GATEWAY.to_string(),
+            royalty: Royalty {
+                treasury_id: env::current_account_id(),
+                treasury: 0,
+                creator: 0,
+            },
+            royalties: LookupMap::new(StorageKey::Royalty),
+            soulbound: false,
+            owner_id,
+            operators: UnorderedMap::new(StorageKey::Operator),
+        };
+        write_state_version();
+        this
+    }
+
+    /// The contract account keeps the rights of the owner, it can redeploy the code anyway.
+    fn is_owner(&self, account_id: &AccountId) -> bool {
+        *account_id == self.owner_id || *account_id == env::current_account_id()
+    }
+
+    fn assert_owner(&self) {
+        require!(
+            self.is_owner(&env::predecessor_account_id()),
+            "Only contract owner"
+        );
+    }
+
+    fn assert_role(&self, role: Role) {
+        let account_id = env::predecessor_account_id();
+        require!(
+            self.is_owner(&account_id) || self.admin_roles_of(account_id).contains(&role),
+            format!("Requires the {:?} role", role)
+        );
+    }
+
+    /// Grants the role to the operator, returns false if it already had it.
+    #[payable]
+    pub fn admin_grant_role(&mut self, account_id: AccountId, role: Role) -> bool {
+        assert_one_yocto();
+        self.assert_owner();
+        let mut roles = self.admin_roles_of(account_id.clone());
+        if roles.contains(&role) {
+            return false;
         }
+        roles.push(role);
+        self.operators.insert(&account_id, &roles);
+        true
     }
 
-    #[private]
+    /// Revokes the role from the operator, returns false if it didn't have it.
+    #[payable]
+    pub fn admin_revoke_role(&mut self, account_id: AccountId, role: Role) -> bool {
+        assert_one_yocto();
+        self.assert_owner();
+        let mut roles = self.admin_roles_of(account_id.clone());
+        let len = roles.len();
+        roles.retain(|granted| *granted != role);
+        if roles.len() == len {
+            return false;
+        }
+        if roles.is_empty() {
+            self.operators.remove(&account_id);
+        } else {
+            self.operators.insert(&account_id, &roles);
+        }
+        true
+    }
+
+    #[payable]
+    pub fn admin_transfer_ownership(&mut self, owner_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.owner_id = owner_id
+    }
+
+    pub fn admin_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    pub fn admin_roles_of(&self, account_id: AccountId) -> Vec<Role> {
+        self.operators.get(&account_id).unwrap_or_default()
+    }
+
+    pub fn admin_operators(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Operator> {
+        let start = from_index.map(|index| index.0).unwrap_or(0) as usize;
+        self.operators
+            .iter()
+            .skip(start)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .map(|(account_id, roles)| Operator { account_id, roles })
+            .collect()
+    }
+
+    #[payable]
     pub fn update_name(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
         self.metadata = LazyOption::new(StorageKey::Metadata, Some(&new_nft_metadata()))
     }
 
-    #[private]
+    #[payable]
+    pub fn update_fallback(&mut self, fallback: Fallback) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.fallback = fallback
+    }
+
+    pub fn avatar_fallback(&self) -> Fallback {
+        self.fallback
+    }
+
+    #[payable]
+    pub fn update_layout(&mut self, layout: Layout) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.layout = layout
+    }
+
+    pub fn avatar_layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Sets the title template of new avatars, `{id}` is replaced with
+    /// the short token id and `{edition}` with the edition number.
+    /// Existing titles follow after `migrate_titles`.
+    #[payable]
+    pub fn update_title_template(&mut self, title_template: String) {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(
+            title_template.contains("{id}") || title_template.contains("{edition}"),
+            "Title template must contain {id} or {edition}"
+        );
+        self.title_template = title_template
+    }
+
+    pub fn avatar_title_template(&self) -> String {
+        self.title_template.clone()
+    }
+
+    /// Sets the gateway `avatar_of` links IPFS media through, the CID is appended to it.
+    #[payable]
+    pub fn update_ipfs_gateway(&mut self, ipfs_gateway: String) {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(
+            ipfs_gateway.starts_with("https://"),
+            "Gateway must be an https URL"
+        );
+        self.ipfs_gateway = ipfs_gateway
+    }
+
+    pub fn avatar_ipfs_gateway(&self) -> String {
+        self.ipfs_gateway.clone()
+    }
+
+    /// Sets the royalty split of tokens minted from now on,
+    /// shares are in basis points of the sale.
+    #[payable]
+    pub fn update_royalty(&mut self, royalty: Royalty) {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(
+            royalty.treasury + royalty.creator <= ROYALTY_MAX,
+            format!("Royalty must be at most {} basis points", ROYALTY_MAX)
+        );
+        self.royalty = royalty
+    }
+
+    pub fn avatar_royalty(&self) -> Royalty {
+        self.royalty.clone()
+    }
+
+    /// Makes every token minted from now on soulbound.
+    #[payable]
+    pub fn update_soulbound(&mut self, soulbound: bool) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.soulbound = soulbound
+    }
+
+    pub fn avatar_soulbound_mode(&self) -> bool {
+        self.soulbound
+    }
+
+    /// Royalty receivers of the token in basis points.
+    pub fn avatar_royalty_of(&self, token_id: TokenId) -> HashMap<AccountId, u32> {
+        self.royalties.get(&token_id).unwrap_or_default()
+    }
+
+    /// Splits `balance` of a sale between the royalty receivers and the owner.
+    pub fn nft_payout(
+        &self,
+        token_id: TokenId,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        let owner_id = self
+            .token
+            .owner_by_id
+            .get(&token_id)
+            .expect("Token not found");
+        let royalty = self.royalties.get(&token_id).unwrap_or_default();
+        let mut payout = HashMap::new();
+        let mut rest = balance.0;
+        for (account_id, share) in royalty {
+            let amount = balance.0 * Balance::from(share) / Balance::from(ROYALTY_BASE);
+            rest -= amount;
+            *payout.entry(account_id).or_insert(0) += amount;
+        }
+        *payout.entry(owner_id).or_insert(0) += rest;
+        if let Some(max_len_payout) = max_len_payout {
+            require!(
+                payout.len() <= max_len_payout as usize,
+                "Payout is longer than max_len_payout"
+            );
+        }
+        Payout {
+            payout: payout
+                .into_iter()
+                .map(|(account_id, amount)| (account_id, U128(amount)))
+                .collect(),
+        }
+    }
+
+    /// Transfers the token like `nft_transfer` and returns the payout of the sale.
+    #[payable]
+    pub fn nft_transfer_payout(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        assert_one_yocto();
+        let payout = self.nft_payout(token_id.clone(), balance, max_len_payout);
+        self.nft_transfer(receiver_id, token_id, approval_id, memo);
+        payout
+    }
+
+    /// Rewrites the titles of up to `limit` tokens starting at `from_index`
+    /// with the current template, returns the number of tokens visited.
+    pub fn migrate_titles(&mut self, from_index: Option<U128>, limit: Option<u64>) -> u64 {
+        self.assert_role(Role::Upgrader);
+        let start = from_index.map(|index| index.0).unwrap_or(0) as usize;
+        let token_ids: Vec<TokenId> = self
+            .token
+            .owner_by_id
+            .iter()
+            .skip(start)
+            .take(limit.unwrap_or(u64::MAX) as usize)
+            .map(|(token_id, _)| token_id)
+            .collect();
+        let by_id = self
+            .token
+            .token_metadata_by_id
+            .as_mut()
+            .expect("Token metadata is not supported");
+        for token_id in token_ids.iter() {
+            if let Some(mut metadata) = by_id.get(token_id) {
+                let edition = token_edition(&metadata);
+                metadata.title = Some(token_title(&self.title_template, token_id, edition));
+                by_id.insert(token_id, &metadata);
+            }
+        }
+        token_ids.len() as u64
+    }
+
     #[init(ignore_state)]
     pub fn migrate() -> Self {
-        let current: Neatar = env::state_read().expect("State doesn't exist");
-        let mut next = Neatar::new();
-        next.token = current.token;
-        next.metadata = current.metadata;
+        let next = VersionedNeatar::read().upgrade();
+        next.assert_role(Role::Upgrader);
+        write_state_version();
         next
     }
 
-    fn current_token(&self, account_id: AccountId) -> Token {
+    /// Version of the stored state layout, readable before `migrate` as well.
+    pub fn state_version() -> u32 {
+        let state = env::storage_read(STATE_KEY).expect("State doesn't exist");
+        read_state_version(&state)
+    }
+
+    fn owned_token(&self, account_id: AccountId) -> Option<Token> {
+        if let Some(token_id) = self.active_tokens.get(&account_id) {
+            return self.token.nft_token(token_id);
+        }
+        // tokens minted before active avatars were introduced
         let list = self.token.nft_tokens_for_owner(account_id, None, None);
-        list.last().cloned().unwrap_or_else(default_token)
+        list.last().cloned()
     }
 
-    pub fn ft_burn(&mut self, token_id: TokenId) {
-        let initial_storage_usage = env::storage_usage();
+    /// Points the active avatar of the account to any other token it holds.
+    fn internal_reset_active(&mut self, account_id: &AccountId) {
+        let next = self
+            .token
+            .tokens_per_owner
+            .as_ref()
+            .and_then(|per_owner| per_owner.get(account_id))
+            .and_then(|owner_tokens| owner_tokens.iter().last());
+        self.internal_set_active(account_id, next.as_ref());
+    }
+
+    fn internal_set_active(&mut self, account_id: &AccountId, token_id: Option<&TokenId>) {
+        match token_id {
+            Some(token_id) => self.active_tokens.insert(account_id, token_id),
+            None => self.active_tokens.remove(account_id),
+        };
+        event::avatar_activate(account_id, token_id);
+    }
+
+    fn internal_on_transfer(
+        &mut self,
+        token_id: &TokenId,
+        from: &AccountId,
+        to: &AccountId,
+        authorized_id: Option<&AccountId>,
+        memo: Option<&str>,
+    ) {
+        event::nft_transfer(from, to, token_id, authorized_id, memo);
+        if self.active_tokens.get(from).as_ref() == Some(token_id) {
+            self.internal_reset_active(from);
+        }
+        if self.active_tokens.get(to).is_none() {
+            self.internal_set_active(to, Some(token_id));
+        }
+    }
+
+    /// Chooses which of the owned tokens is returned by `avatar_of`.
+    pub fn avatar_set_active(&mut self, token_id: TokenId) {
         let owner_id = self
             .token
             .owner_by_id
             .get(&token_id)
             .expect("Not found token");
         require!(owner_id == env::predecessor_account_id(), "Only owner");
-        match self
+        self.internal_set_active(&owner_id, Some(&token_id));
+    }
+
+    fn current_token(&self, account_id: AccountId) -> Token {
+        self.owned_token(account_id).unwrap_or_else(default_token)
+    }
+
+    pub fn ft_burn(&mut self, token_id: TokenId) {
+        let owner_id = self
             .token
-            .tokens_per_owner
-            .as_mut()
-            .and_then(|per_owner| per_owner.remove(&owner_id))
-        {
-            None => {}
-            Some(mut set) => set.clear(),
-        };
+            .owner_by_id
+            .get(&token_id)
+            .expect("Not found token");
+        require!(owner_id == env::predecessor_account_id(), "Only owner");
+        self.internal_burn(&token_id, &owner_id, None, env::predecessor_account_id());
+    }
+
+    /// Removes the token and transfers the freed storage cost to `refund_id`.
+    fn internal_burn(
+        &mut self,
+        token_id: &TokenId,
+        owner_id: &AccountId,
+        authorized_id: Option<&AccountId>,
+        refund_id: AccountId,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        if let Some(tokens_per_owner) = self.token.tokens_per_owner.as_mut() {
+            if let Some(mut owner_tokens) = tokens_per_owner.get(owner_id) {
+                owner_tokens.remove(token_id);
+                if owner_tokens.is_empty() {
+                    tokens_per_owner.remove(owner_id);
+                } else {
+                    tokens_per_owner.insert(owner_id, &owner_tokens);
+                }
+            }
+        }
         self.token
             .approvals_by_id
             .as_mut()
-            .and_then(|by_id| by_id.remove(&token_id));
-        self.token
+            .and_then(|by_id| by_id.remove(token_id));
+        let metadata = self
+            .token
             .token_metadata_by_id
             .as_mut()
-            .and_then(|by_id| by_id.remove(&token_id));
-        self.token.owner_by_id.remove(&token_id);
+            .and_then(|by_id| by_id.remove(token_id));
+        if let Some(edition) = metadata.as_ref().and_then(token_edition) {
+            self.editions.remove(&edition);
+        }
+        self.royalties.remove(token_id);
+        self.token.owner_by_id.remove(token_id);
+        event::nft_burn(owner_id, token_id, authorized_id);
+        if self.active_tokens.get(owner_id).as_ref() == Some(token_id) {
+            self.internal_reset_active(owner_id);
+        }
         // make refund for storage free
-        let storage_free = initial_storage_usage
-            .checked_sub(env::storage_usage())
-            .unwrap_or_default();
+        let storage_free = initial_storage_usage.saturating_sub(env::storage_usage());
         log!("storage free: {}", storage_free);
+        self.internal_refund_storage(refund_id, storage_free);
+    }
+
+    /// Pays for `storage_used` bytes from the prepaid storage of `account_id`
+    /// topped up by the attached deposit, the prepaid storage is only spent
+    /// by the account itself, otherwise the attached deposit alone pays.
+    fn internal_pay_storage(&mut self, account_id: &AccountId, storage_used: StorageUsage) {
+        let balance = match self.storage_deposits.get(account_id) {
+            Some(balance) if *account_id == env::predecessor_account_id() => {
+                balance + env::attached_deposit()
+            }
+            _ => return refund_deposit(storage_used),
+        };
+        let required_cost = env::storage_byte_cost() * Balance::from(storage_used);
+        let available = balance.saturating_sub(self.storage_balance_min());
+        require!(
+            required_cost <= available,
+            format!(
+                "Not enough storage balance, {} yoctoNEAR required, available {}",
+                required_cost, available
+            )
+        );
+        self.storage_deposits
+            .insert(account_id, &(balance - required_cost));
+    }
+
+    /// Returns the cost of `storage_free` bytes to the prepaid storage of
+    /// `account_id`, or transfers it when the account is not registered.
+    fn internal_refund_storage(&mut self, account_id: AccountId, storage_free: StorageUsage) {
         let refund = env::storage_byte_cost() * Balance::from(storage_free);
-        if refund > 1 {
-            Promise::new(env::predecessor_account_id()).transfer(refund);
+        if let Some(balance) = self.storage_deposits.get(&account_id) {
+            self.storage_deposits
+                .insert(&account_id, &(balance + refund));
+        } else if refund > 1 {
+            Promise::new(account_id).transfer(refund);
         }
     }
 
-    pub fn avatar_of(&self, account_id: AccountId) -> String {
-        let token = self.current_token(account_id);
-        let media = token.metadata.unwrap().media.unwrap();
-        format!("data:image/{}", media)
+    /// Revokes the approval of `account_id` or all approvals when `None`,
+    /// the freed storage goes back to the prepaid storage of the owner.
+    fn internal_revoke(
+        &mut self,
+        token_id: &TokenId,
+        owner_id: AccountId,
+        account_id: Option<&AccountId>,
+    ) {
+        assert_one_yocto();
+        require!(
+            env::predecessor_account_id() == owner_id,
+            "Predecessor must be token owner."
+        );
+        let initial_storage_usage = env::storage_usage();
+        let approvals_by_id = self
+            .token
+            .approvals_by_id
+            .as_mut()
+            .expect("NFT does not support Approval Management");
+        if let Some(mut approved_account_ids) = approvals_by_id.get(token_id) {
+            match account_id {
+                Some(account_id) => {
+                    approved_account_ids.remove(account_id);
+                }
+                None => approved_account_ids.clear(),
+            }
+            if approved_account_ids.is_empty() {
+                approvals_by_id.remove(token_id);
+            } else {
+                approvals_by_id.insert(token_id, &approved_account_ids);
+            }
+        }
+        let storage_free = initial_storage_usage.saturating_sub(env::storage_usage());
+        self.internal_refund_storage(owner_id, storage_free);
     }
 
-    #[payable]
-    pub fn avatar_create(&mut self) -> String {
-        let owner_id = env::signer_account_id();
-        self.avatar_create_for(owner_id)
+    fn storage_balance_min(&self) -> Balance {
+        env::storage_byte_cost() * Balance::from(self.account_storage_usage)
     }
 
-    pub fn avatar_burn(&mut self) {
-        self.ft_burn(self.current_token(env::predecessor_account_id()).token_id)
+    /// Avatar of the account, `fallback` overrides the contract setting
+    /// for accounts without a minted avatar.
+    pub fn avatar_of(&self, account_id: AccountId, fallback: Option<Fallback>) -> String {
+        self.account_avatar(account_id, fallback.unwrap_or(self.fallback))
+            .avatar
     }
 
-    #[private]
-    pub fn avatar_burn_for(&mut self, owner_id: AccountId) {
-        // TODO
+    /// Same as `avatar_of` for up to `AVATARS_OF_MAX` accounts at once,
+    /// in the order of `account_ids`.
+    pub fn avatars_of(
+        &self,
+        account_ids: Vec<AccountId>,
+        fallback: Option<Fallback>,
+    ) -> Vec<AccountAvatar> {
+        require!(
+            account_ids.len() <= AVATARS_OF_MAX,
+            format!("At most {} accounts per call", AVATARS_OF_MAX)
+        );
+        let fallback = fallback.unwrap_or(self.fallback);
+        account_ids
+            .into_iter()
+            .map(|account_id| self.account_avatar(account_id, fallback))
+            .collect()
     }
 
+    fn account_avatar(&self, account_id: AccountId, fallback: Fallback) -> AccountAvatar {
+        let (avatar, exist) = match self.owned_token(account_id.clone()) {
+            Some(token) => (self.token_media_url(token), true),
+            None => match fallback {
+                Fallback::Logo => (self.token_media_url(default_token()), false),
+                Fallback::Identicon => (self.identicon_of(account_id.clone()), false),
+            },
+        };
+        AccountAvatar {
+            account_id,
+            avatar,
+            exist,
+        }
+    }
+
+    fn token_media_url(&self, token: Token) -> String {
+        self.media_url(&token.metadata.unwrap().media.unwrap())
+    }
+
+    /// Gateway URL for media on IPFS, data URI for inline media.
+    fn media_url(&self, media: &str) -> String {
+        match media.strip_prefix(IPFS_SCHEME) {
+            Some(cid) => format!("{}{}", self.ipfs_gateway, cid),
+            None => format!("data:image/{}", media),
+        }
+    }
+
+    /// Whether the account owns a minted avatar rather than the default logo.
+    pub fn avatar_exist(&self, account_id: AccountId) -> bool {
+        self.owned_token(account_id).is_some()
+    }
+
+    /// Token id of the account's avatar, `None` if the default logo is shown.
+    pub fn avatar_id_of(&self, account_id: AccountId) -> Option<TokenId> {
+        self.owned_token(account_id).map(|token| token.token_id)
+    }
+
+    /// Mint timestamp (nanoseconds) of the account's avatar.
+    pub fn avatar_issued_at(&self, account_id: AccountId) -> Option<String> {
+        self.owned_token(account_id)
+            .and_then(|token| token.metadata)
+            .and_then(|metadata| metadata.issued_at)
+    }
+
+    /// Edition of the token, `None` for tokens minted before editions.
+    pub fn avatar_edition(&self, token_id: TokenId) -> Option<u64> {
+        self.token
+            .token_metadata_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(&token_id))
+            .as_ref()
+            .and_then(token_edition)
+    }
+
+    /// Token minted as `edition`, `None` if it was burned.
+    pub fn avatar_by_edition(&self, edition: u64) -> Option<Token> {
+        self.editions
+            .get(&edition)
+            .and_then(|token_id| self.token.nft_token(token_id))
+    }
+
+    /// Number of avatars minted so far, burned ones included.
+    pub fn avatar_minted(&self) -> u64 {
+        self.minted
+    }
+
+    /// Sha256 of the media of the account's avatar, enough to tell whether
+    /// a cached one is stale. `None` when `avatar_of` returns a fallback.
+    pub fn avatar_hash_of(&self, account_id: AccountId) -> Option<Base64VecU8> {
+        self.owned_token(account_id)
+            .and_then(|token| token.metadata)
+            .and_then(|metadata| metadata.media_hash)
+    }
+
+    /// Avatar token of the account with the media left out, its id is the CID of the media.
+    pub fn avatar_token_of(&self, account_id: AccountId) -> Option<Token> {
+        self.owned_token(account_id).map(|mut token| {
+            if let Some(metadata) = token.metadata.as_mut() {
+                metadata.media = None;
+            }
+            token
+        })
+    }
+
+    /// Same as `avatar_of` with the svg sized to `size` pixels.
+    pub fn avatar_of_size(
+        &self,
+        account_id: AccountId,
+        size: u32,
+        fallback: Option<Fallback>,
+    ) -> String {
+        require!(
+            size > 0 && size <= AVATAR_SIZE_MAX,
+            format!("Size must be from 1 to {}", AVATAR_SIZE_MAX)
+        );
+        let svg = match self.owned_token(account_id.clone()) {
+            Some(token) => {
+                let media = token.metadata.unwrap().media.unwrap();
+                match unpack_data_image(&media) {
+                    Some((media_type, data)) if media_type == MediaType::Svg.subtype() => {
+                        resize_svg(&String::from_utf8_lossy(&data), size)
+                    }
+                    _ => return self.media_url(&media),
+                }
+            }
+            None => match fallback.unwrap_or(self.fallback) {
+                Fallback::Logo => resize_svg(&LOGO, size),
+                Fallback::Identicon => {
+                    identicon::make_sized(&Entropy::Identity.seed(&account_id), self.layout, size)
+                }
+            },
+        };
+        format!(
+            "data:image/{}",
+            pack_data_image(svg.as_bytes(), MediaType::Svg)
+        )
+    }
+
+    /// Mints an identicon, `soulbound` keeps it with the account for good.
     #[payable]
-    #[private]
-    pub fn avatar_create_for(&mut self, owner_id: AccountId) -> String {
-        let initial_storage_usage = env::storage_usage();
-        let hash: &[u8] =
-            &env::sha256(format!("{}-{}", owner_id, env::block_timestamp()).as_bytes());
-        let svg = identicon::make(hash);
-        let contract_id = env::current_account_id();
-        let token = new_token(svg, None);
-        let token_id = token.token_id;
-        let metadata = token.metadata.unwrap();
-        let media = metadata.media.clone().unwrap_or_default();
+    pub fn avatar_create(&mut self, entropy: Option<Entropy>, soulbound: Option<bool>) -> String {
+        let owner_id = env::signer_account_id();
+        let entropy = user_entropy(entropy);
+        let soulbound = self.soulbound || soulbound == Some(true);
+        let token = self.internal_create(&owner_id, entropy.seed(&owner_id), soulbound);
+        token.metadata.unwrap().media.unwrap()
+    }
+
+    /// Mints the caller's own svg, the markup is sanitized first so
+    /// the avatar is safe to show in an `<img>` anywhere.
+    #[payable]
+    pub fn avatar_upload(&mut self, svg: String) -> String {
+        require!(
+            svg.len() <= AVATAR_UPLOAD_MAX,
+            format!("Svg must be at most {} bytes", AVATAR_UPLOAD_MAX)
+        );
+        let svg = sanitize::sanitize_svg(&svg).expect("Invalid svg");
+        let owner_id = env::predecessor_account_id();
+        let options = MintOptions {
+            creator_id: Some(&owner_id),
+            soulbound: self.soulbound,
+        };
+        let token = self.internal_create_inline(&owner_id, svg.as_bytes(), MediaType::Svg, options);
+        token.metadata.unwrap().media.unwrap()
+    }
+
+    /// Mints the caller's own image, the type is detected by the content,
+    /// svg is sanitized the same way as in `avatar_upload`.
+    #[payable]
+    pub fn avatar_upload_image(&mut self, data: Base64VecU8) -> String {
+        require!(
+            data.0.len() <= AVATAR_UPLOAD_MAX,
+            format!("Image must be at most {} bytes", AVATAR_UPLOAD_MAX)
+        );
+        let media_type = MediaType::sniff(&data.0).expect("Unsupported media type");
+        if media_type == MediaType::Svg {
+            return self.avatar_upload(String::from_utf8(data.0).expect("Invalid svg"));
+        }
+        let owner_id = env::predecessor_account_id();
+        let options = MintOptions {
+            creator_id: Some(&owner_id),
+            soulbound: self.soulbound,
+        };
+        let token = self.internal_create_inline(&owner_id, &data.0, media_type, options);
+        token.metadata.unwrap().media.unwrap()
+    }
+
+    /// Mints an avatar kept on IPFS, `cid` must be the CIDv1 of the raw
+    /// content (`ipfs add --cid-version 1 --raw-leaves`) with sha256 `media_hash`.
+    #[payable]
+    pub fn avatar_upload_ipfs(
+        &mut self,
+        cid: String,
+        media_hash: Base64VecU8,
+        media_type: MediaType,
+    ) -> String {
+        let token_id = ipfs_token_id(&cid, &media_hash.0);
+        let media = format!("{}{}", IPFS_SCHEME, token_id);
+        let owner_id = env::predecessor_account_id();
+        let options = MintOptions {
+            creator_id: Some(&owner_id),
+            soulbound: self.soulbound,
+        };
+        let media = NewMedia {
+            media,
+            media_hash: media_hash.0,
+            media_type,
+        };
+        let token = self.internal_create_media(&owner_id, token_id, media, options);
+        token.metadata.unwrap().media.unwrap()
+    }
+
+    /// Whether the token is bound to its owner for good.
+    pub fn avatar_soulbound(&self, token_id: TokenId) -> bool {
         self.token
-            .internal_mint(token_id.clone(), contract_id.clone(), Some(metadata));
-        env::promise_create(
-            contract_id,
-            "nft_transfer",
-            json!({
-                "token_id": token_id,
-                "receiver_id": owner_id,
-            })
-            .to_string()
-            .as_bytes(),
-            ONE_YOCTO,
-            SINGLE_CALL_GAS,
+            .token_metadata_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(&token_id))
+            .and_then(|metadata| token_extra(&metadata))
+            .map_or(false, |extra| extra.soulbound)
+    }
+
+    fn assert_transferable(&self, token_id: &TokenId) {
+        require!(
+            !self.avatar_soulbound(token_id.clone()),
+            "Soulbound avatar can't be transferred"
         );
-        let storage_usage = env::storage_usage()
-            .checked_sub(initial_storage_usage)
-            .unwrap_or_default();
+    }
+
+    /// Media type of the token image.
+    pub fn avatar_media_type(&self, token_id: TokenId) -> Option<MediaType> {
+        self.token
+            .token_metadata_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(&token_id))
+            .map(|metadata| token_media_type(&metadata))
+    }
+
+    /// Identicon the account gets with `Entropy::Identity`, rendered without minting.
+    pub fn identicon_of(&self, account_id: AccountId) -> String {
+        let svg = identicon::make(&Entropy::Identity.seed(&account_id), self.layout);
+        format!(
+            "data:image/{}",
+            pack_data_image(svg.as_bytes(), MediaType::Svg)
+        )
+    }
+
+    /// First step of a commit–reveal mint, `hash` is `sha256(secret)`.
+    /// The caller pays for the storage of the commitment until the reveal.
+    #[payable]
+    pub fn avatar_commit(&mut self, hash: Base64VecU8) {
+        require!(hash.0.len() == 32, "Hash must be sha256");
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let commitment = Commitment {
+            hash: hash.0,
+            block_height: env::block_height(),
+        };
+        self.commitments.insert(&account_id, &commitment);
+        let storage_usage = env::storage_usage().saturating_sub(initial_storage_usage);
+        self.internal_pay_storage(&account_id, storage_usage);
+    }
+
+    /// Second step of a commit–reveal mint, the identicon is derived from
+    /// the secret and the random seed of the reveal block.
+    #[payable]
+    pub fn avatar_reveal(&mut self, secret: Base64VecU8) -> String {
+        let owner_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let commitment = self
+            .commitments
+            .remove(&owner_id)
+            .expect("Not found commitment");
+        let storage_free = initial_storage_usage.saturating_sub(env::storage_usage());
+        self.internal_refund_storage(owner_id.clone(), storage_free);
+        require!(
+            env::block_height() > commitment.block_height,
+            "Reveal in a later block than commit"
+        );
+        require!(
+            env::sha256(&secret.0) == commitment.hash,
+            "Secret does not match commitment"
+        );
+        let seed = env::sha256(&[owner_id.as_bytes(), &secret.0, &env::random_seed()].concat());
+        let token = self.internal_create(&owner_id, seed, self.soulbound);
+        token.metadata.unwrap().media.unwrap()
+    }
+
+    pub fn avatar_burn(&mut self) {
+        self.ft_burn(self.current_token(env::predecessor_account_id()).token_id)
+    }
+
+    /// Burns the avatar of `owner_id`, the freed storage is refunded
+    /// to `refund_id` or to the owner when omitted.
+    pub fn avatar_burn_for(&mut self, owner_id: AccountId, refund_id: Option<AccountId>) {
+        self.assert_role(Role::Moderator);
+        let token_id = self
+            .owned_token(owner_id.clone())
+            .expect("Not found avatar")
+            .token_id;
+        let refund_id = refund_id.unwrap_or_else(|| owner_id.clone());
+        let authorized_id = env::predecessor_account_id();
+        self.internal_burn(&token_id, &owner_id, Some(&authorized_id), refund_id);
+    }
+
+    /// Burns the active avatar of the caller and mints a new one instead,
+    /// a soulbound avatar is replaced with a soulbound one.
+    #[payable]
+    pub fn avatar_regenerate(&mut self, entropy: Option<Entropy>) -> String {
+        let owner_id = env::predecessor_account_id();
+        let entropy = user_entropy(entropy);
+        let old_token_id = self
+            .owned_token(owner_id.clone())
+            .expect("Not found avatar")
+            .token_id;
+        let soulbound = self.soulbound || self.avatar_soulbound(old_token_id.clone());
+        self.internal_burn(&old_token_id, &owner_id, None, owner_id.clone());
+        let token = self.internal_create(&owner_id, entropy.seed(&owner_id), soulbound);
+        // the burn may have activated another token the owner holds
+        if self.active_tokens.get(&owner_id).as_ref() != Some(&token.token_id) {
+            self.internal_set_active(&owner_id, Some(&token.token_id));
+        }
+        event::avatar_regenerate(&owner_id, &old_token_id, &token.token_id);
+        token.metadata.unwrap().media.unwrap()
+    }
+
+    #[payable]
+    pub fn avatar_create_for(
+        &mut self,
+        owner_id: AccountId,
+        entropy: Option<Entropy>,
+        soulbound: Option<bool>,
+    ) -> String {
+        self.assert_role(Role::Minter);
+        let seed = entropy.unwrap_or(Entropy::Random).seed(&owner_id);
+        let soulbound = self.soulbound || soulbound == Some(true);
+        let token = self.internal_create(&owner_id, seed, soulbound);
+        token.metadata.unwrap().media.unwrap()
+    }
+
+    /// Mints the identicon of `seed` to `owner_id`.
+    fn internal_create(&mut self, owner_id: &AccountId, seed: Vec<u8>, soulbound: bool) -> Token {
+        let svg = identicon::make(&seed, self.layout);
+        let options = MintOptions {
+            creator_id: None,
+            soulbound,
+        };
+        self.internal_create_inline(owner_id, svg.as_bytes(), MediaType::Svg, options)
+    }
+
+    /// Mints the image to `owner_id` with the data inlined in `media`.
+    fn internal_create_inline(
+        &mut self,
+        owner_id: &AccountId,
+        data: &[u8],
+        media_type: MediaType,
+        options: MintOptions,
+    ) -> Token {
+        let media = NewMedia {
+            media: pack_data_image(data, media_type),
+            media_hash: env::sha256(data),
+            media_type,
+        };
+        self.internal_create_media(owner_id, token_id_of(data), media, options)
+    }
+
+    /// Mints the media to `owner_id`, the storage of the whole mint is paid
+    /// from the prepaid storage or the attached deposit.
+    fn internal_create_media(
+        &mut self,
+        owner_id: &AccountId,
+        token_id: TokenId,
+        media: NewMedia,
+        options: MintOptions,
+    ) -> Token {
+        let initial_storage_usage = env::storage_usage();
+        self.minted += 1;
+        let edition = self.minted;
+        let title = token_title(&self.title_template, &token_id, Some(edition));
+        let extra = TokenExtra {
+            edition,
+            media_type: media.media_type,
+            soulbound: options.soulbound,
+        };
+        let metadata = new_token_metadata(media.media, media.media_hash, title, Some(extra));
+        self.editions.insert(&edition, &token_id);
+        let royalty = self.royalty.split(options.creator_id);
+        if !royalty.is_empty() {
+            self.royalties.insert(&token_id, &royalty);
+        }
+        let token = self.internal_mint(token_id, owner_id, metadata);
+        event::nft_mint(owner_id, &token.token_id);
+        // a mint doesn't override the avatar the owner chose
+        if self.active_tokens.get(owner_id).is_none() {
+            self.internal_set_active(owner_id, Some(&token.token_id));
+        }
+        let storage_usage = env::storage_usage().saturating_sub(initial_storage_usage);
         log!("storage usage: {}", storage_usage);
-        media
+        self.internal_pay_storage(owner_id, storage_usage);
+        token
+    }
+
+    /// Same as `NonFungibleToken::internal_mint` but leaves the deposit
+    /// to the caller, so the active token is paid for too.
+    fn internal_mint(
+        &mut self,
+        token_id: TokenId,
+        owner_id: &AccountId,
+        metadata: TokenMetadata,
+    ) -> Token {
+        require!(
+            self.token.owner_by_id.get(&token_id).is_none(),
+            "token_id must be unique"
+        );
+        self.token.owner_by_id.insert(&token_id, owner_id);
+        if let Some(by_id) = self.token.token_metadata_by_id.as_mut() {
+            by_id.insert(&token_id, &metadata);
+        }
+        if let Some(tokens_per_owner) = self.token.tokens_per_owner.as_mut() {
+            let mut owner_tokens = tokens_per_owner.get(owner_id).unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::TokensPerOwner {
+                    account_hash: env::sha256(owner_id.as_bytes()),
+                })
+            });
+            owner_tokens.insert(&token_id);
+            tokens_per_owner.insert(owner_id, &owner_tokens);
+        }
+        Token {
+            token_id,
+            owner_id: owner_id.clone(),
+            metadata: Some(metadata),
+            approved_account_ids: Some(HashMap::new()),
+        }
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenCore for Neatar {
+    #[payable]
+    fn nft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    ) {
+        let owner_id = self
+            .token
+            .owner_by_id
+            .get(&token_id)
+            .expect("Token not found");
+        self.assert_transferable(&token_id);
+        self.token.nft_transfer(
+            receiver_id.clone(),
+            token_id.clone(),
+            approval_id,
+            memo.clone(),
+        );
+        let sender_id = env::predecessor_account_id();
+        let authorized_id = Some(&sender_id).filter(|&sender_id| sender_id != &owner_id);
+        self.internal_on_transfer(
+            &token_id,
+            &owner_id,
+            &receiver_id,
+            authorized_id,
+            memo.as_deref(),
+        );
+    }
+
+    #[payable]
+    fn nft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        let owner_id = self
+            .token
+            .owner_by_id
+            .get(&token_id)
+            .expect("Token not found");
+        self.assert_transferable(&token_id);
+        let result = self.token.nft_transfer_call(
+            receiver_id.clone(),
+            token_id.clone(),
+            approval_id,
+            memo.clone(),
+            msg,
+        );
+        let sender_id = env::predecessor_account_id();
+        let authorized_id = Some(&sender_id).filter(|&sender_id| sender_id != &owner_id);
+        self.internal_on_transfer(
+            &token_id,
+            &owner_id,
+            &receiver_id,
+            authorized_id,
+            memo.as_deref(),
+        );
+        result
+    }
+
+    fn nft_token(&self, token_id: TokenId) -> Option<Token> {
+        self.token.nft_token(token_id)
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenResolver for Neatar {
+    #[private]
+    fn nft_resolve_transfer(
+        &mut self,
+        previous_owner_id: AccountId,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approved_account_ids: Option<HashMap<AccountId, u64>>,
+    ) -> bool {
+        let transferred = self.token.nft_resolve_transfer(
+            previous_owner_id.clone(),
+            receiver_id.clone(),
+            token_id.clone(),
+            approved_account_ids,
+        );
+        if !transferred
+            && self.token.owner_by_id.get(&token_id).as_ref() == Some(&previous_owner_id)
+        {
+            self.internal_on_transfer(&token_id, &receiver_id, &previous_owner_id, None, None);
+        }
+        transferred
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenApproval for Neatar {
+    /// Approvals of accounts with prepaid storage are paid from it.
+    #[payable]
+    fn nft_approve(
+        &mut self,
+        token_id: TokenId,
+        account_id: AccountId,
+        msg: Option<String>,
+    ) -> Option<Promise> {
+        let owner_id = self
+            .token
+            .owner_by_id
+            .get(&token_id)
+            .expect("Token not found");
+        require!(
+            !self.avatar_soulbound(token_id.clone()),
+            "Soulbound avatar can't be approved"
+        );
+        if self.storage_deposits.get(&owner_id).is_none() {
+            return self.token.nft_approve(token_id, account_id, msg);
+        }
+        require!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        require!(
+            env::predecessor_account_id() == owner_id,
+            "Predecessor must be token owner."
+        );
+        let initial_storage_usage = env::storage_usage();
+        let approvals_by_id = self
+            .token
+            .approvals_by_id
+            .as_mut()
+            .expect("NFT does not support Approval Management");
+        let next_approval_id_by_id = self
+            .token
+            .next_approval_id_by_id
+            .as_mut()
+            .expect("next_approval_by_id must be set for approval ext");
+        let mut approved_account_ids = approvals_by_id.get(&token_id).unwrap_or_default();
+        let approval_id = next_approval_id_by_id.get(&token_id).unwrap_or(1);
+        approved_account_ids.insert(account_id.clone(), approval_id);
+        approvals_by_id.insert(&token_id, &approved_account_ids);
+        next_approval_id_by_id.insert(&token_id, &(approval_id + 1));
+        let storage_used = env::storage_usage().saturating_sub(initial_storage_usage);
+        self.internal_pay_storage(&owner_id, storage_used);
+
+        msg.map(|msg| {
+            ext_approval_receiver::nft_on_approve(
+                token_id,
+                owner_id,
+                approval_id,
+                msg,
+                account_id,
+                0,
+                env::prepaid_gas() - GAS_FOR_NFT_APPROVE,
+            )
+        })
+    }
+
+    /// Approvals of accounts with prepaid storage are refunded to it.
+    #[payable]
+    fn nft_revoke(&mut self, token_id: TokenId, account_id: AccountId) {
+        let owner_id = self
+            .token
+            .owner_by_id
+            .get(&token_id)
+            .expect("Token not found");
+        if self.storage_deposits.get(&owner_id).is_none() {
+            return self.token.nft_revoke(token_id, account_id);
+        }
+        self.internal_revoke(&token_id, owner_id, Some(&account_id));
+    }
+
+    #[payable]
+    fn nft_revoke_all(&mut self, token_id: TokenId) {
+        let owner_id = self
+            .token
+            .owner_by_id
+            .get(&token_id)
+            .expect("Token not found");
+        if self.storage_deposits.get(&owner_id).is_none() {
+            return self.token.nft_revoke_all(token_id);
+        }
+        self.internal_revoke(&token_id, owner_id, None);
+    }
+
+    fn nft_is_approved(
+        &self,
+        token_id: TokenId,
+        approved_account_id: AccountId,
+        approval_id: Option<u64>,
+    ) -> bool {
+        self.token
+            .nft_is_approved(token_id, approved_account_id, approval_id)
     }
 }
 
-near_contract_standards::impl_non_fungible_token_core!(Neatar, token);
-near_contract_standards::impl_non_fungible_token_approval!(Neatar, token);
 near_contract_standards::impl_non_fungible_token_enumeration!(Neatar, token);
 
 #[near_bindgen]
@@ -264,13 +1798,121 @@ impl NonFungibleTokenMetadataProvider for Neatar {
     }
 }
 
+/// Prepaid storage for avatars, approvals and other writes of an account,
+/// the registration itself keeps `storage_balance_bounds().min` locked.
+#[near_bindgen]
+impl StorageManagement for Neatar {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let min_balance = self.storage_balance_min();
+        let (balance, refund) = match self.storage_deposits.get(&account_id) {
+            Some(_) if registration_only == Some(true) => (None, amount),
+            Some(balance) => (Some(balance + amount), 0),
+            None => {
+                require!(
+                    amount >= min_balance,
+                    "The attached deposit is less than the minimum storage balance"
+                );
+                if registration_only == Some(true) {
+                    (Some(min_balance), amount - min_balance)
+                } else {
+                    (Some(amount), 0)
+                }
+            }
+        };
+        if let Some(balance) = balance {
+            self.storage_deposits.insert(&account_id, &balance);
+        }
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let balance = self
+            .storage_deposits
+            .get(&account_id)
+            .expect("The account is not registered");
+        let available = balance - self.storage_balance_min();
+        let amount = amount.map(|amount| amount.0).unwrap_or(available);
+        require!(
+            amount <= available,
+            "The amount is greater than the available storage balance"
+        );
+        self.storage_deposits
+            .insert(&account_id, &(balance - amount));
+        if amount > 0 {
+            Promise::new(account_id.clone()).transfer(amount);
+        }
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    /// With `force` the avatars of the account are burned first.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        if self.storage_deposits.get(&account_id).is_none() {
+            log!("The account {} is not registered", account_id);
+            return false;
+        }
+        let token_ids: Vec<TokenId> = self
+            .token
+            .tokens_per_owner
+            .as_ref()
+            .and_then(|per_owner| per_owner.get(&account_id))
+            .map(|owner_tokens| owner_tokens.to_vec())
+            .unwrap_or_default();
+        require!(
+            token_ids.is_empty() || force == Some(true),
+            "Can't unregister the account with avatars, burn them first or use force"
+        );
+        for token_id in token_ids {
+            self.internal_burn(&token_id, &account_id, None, account_id.clone());
+        }
+        let balance = self.storage_deposits.remove(&account_id).unwrap();
+        if balance > 0 {
+            Promise::new(account_id).transfer(balance);
+        }
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: self.storage_balance_min().into(),
+            max: None,
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_deposits
+            .get(&account_id)
+            .map(|balance| StorageBalance {
+                total: balance.into(),
+                available: balance.saturating_sub(self.storage_balance_min()).into(),
+            })
+    }
+}
+
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod unit {
     use super::*;
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::mock::{Receipt, VmAction};
+    use near_sdk::test_utils::{accounts, get_created_receipts, get_logs, VMContextBuilder};
     use near_sdk::testing_env;
 
     const MINT_STORAGE_COST: u128 = 25900000000000000000000;
+    const COMMIT_STORAGE_COST: u128 = 2000000000000000000000;
     // 0.0259
 
     fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
@@ -282,6 +1924,27 @@ mod unit {
         builder
     }
 
+    fn create_avatars(
+        context: &mut VMContextBuilder,
+        contract: &mut Neatar,
+        count: u64,
+    ) -> Vec<TokenId> {
+        let mut token_ids = Vec::new();
+        for timestamp in 1..=count {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_STORAGE_COST)
+                .random_seed(vec![timestamp as u8; 32])
+                .predecessor_account_id(accounts(0))
+                .signer_account_id(accounts(0))
+                .build());
+            contract.avatar_create(None, None);
+            let token = contract.avatar_by_edition(contract.avatar_minted());
+            token_ids.push(token.unwrap().token_id);
+        }
+        token_ids
+    }
+
     #[test]
     fn test_new() {
         let mut context = get_context(accounts(1));
@@ -312,7 +1975,7 @@ mod unit {
             .signer_account_id(accounts(0))
             .build());
 
-        let avatar = contract.avatar_create();
+        let avatar = contract.avatar_create(None, None);
         assert_eq!(1071, avatar.len());
 
         let token = contract
@@ -323,27 +1986,1634 @@ mod unit {
         assert_eq!(token.owner_id, accounts(0));
         let metadata = token.metadata.unwrap();
         assert_eq!(1071, metadata.media.clone().unwrap().len());
-        assert_eq!(10, metadata.title.unwrap().len());
+        assert_eq!(12, metadata.title.unwrap().len());
         assert_eq!(token.approved_account_ids.unwrap().len(), 0);
     }
 
     #[test]
-    fn test_avatar_burn() {
+    fn test_avatar_create_refund() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        let attached_deposit = MINT_STORAGE_COST * 40;
+        let initial_storage_usage = env::storage_usage();
+        testing_env!(context
+            .storage_usage(initial_storage_usage)
+            .attached_deposit(attached_deposit)
+            .build());
+        contract.avatar_create(None, None);
+
+        let storage_cost =
+            env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert_eq!(
+            get_created_receipts(),
+            vec![Receipt {
+                receipt_indices: vec![],
+                receiver_id: accounts(0),
+                actions: vec![VmAction::Transfer {
+                    deposit: attached_deposit - storage_cost
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "yoctoNEAR to cover storage, attached 12950000000000000000000")]
+    fn test_avatar_create_short_deposit() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
         let mut contract = Neatar::new();
 
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
-            .signer_account_id(accounts(0))
+            .attached_deposit(MINT_STORAGE_COST / 2)
+            .build());
+        contract.avatar_create(None, None);
+    }
+
+    fn storage_total(contract: &Neatar, account_id: AccountId) -> Balance {
+        contract.storage_balance_of(account_id).unwrap().total.0
+    }
+
+    #[test]
+    fn test_storage_deposit_withdraw() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let min = contract.storage_balance_bounds().min.0;
+        assert!(min > 0);
+        assert!(contract.storage_balance_of(accounts(1)).is_none());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(min + 100)
+            .build());
+        let balance = contract.storage_deposit(None, Some(true));
+        assert_eq!((min, 0), (balance.total.0, balance.available.0));
+
+        contract.storage_deposit(None, None);
+        assert_eq!(min * 2 + 100, storage_total(&contract, accounts(1)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
             .build());
+        let balance = contract.storage_withdraw(Some(U128(100)));
+        assert_eq!(min * 2, balance.total.0);
+        let balance = contract.storage_withdraw(None);
+        assert_eq!((min, 0), (balance.total.0, balance.available.0));
+        assert!(contract.storage_unregister(None));
+        assert!(contract.storage_balance_of(accounts(1)).is_none());
+    }
+
+    #[test]
+    fn test_avatar_create_prepaid() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let deposit = contract.storage_balance_bounds().min.0 + MINT_STORAGE_COST;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .build());
+        contract.storage_deposit(None, None);
+
+        let initial_storage_usage = env::storage_usage();
+        testing_env!(context
+            .storage_usage(initial_storage_usage)
+            .attached_deposit(0)
+            .build());
+        contract.avatar_create(None, None);
+        let storage_cost =
+            env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert_eq!(
+            deposit - storage_cost,
+            storage_total(&contract, accounts(1))
+        );
+        assert!(get_created_receipts().is_empty());
+
+        // the freed storage goes back to the prepaid balance
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.avatar_burn();
+        assert_eq!(deposit, storage_total(&contract, accounts(1)));
+        assert!(get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn test_avatar_create_for_prepaid() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let deposit = contract.storage_balance_bounds().min.0 + MINT_STORAGE_COST;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .build());
+        contract.storage_deposit(Some(accounts(1)), None);
+
+        // a mint for another account is paid by the caller
+        let initial_storage_usage = env::storage_usage();
+        testing_env!(context
+            .storage_usage(initial_storage_usage)
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.avatar_create_for(accounts(1), None, None);
+        assert!(contract.avatar_exist(accounts(1)));
+        assert_eq!(deposit, storage_total(&contract, accounts(1)));
+        let storage_cost =
+            env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert_eq!(
+            get_created_receipts(),
+            vec![Receipt {
+                receipt_indices: vec![],
+                receiver_id: accounts(0),
+                actions: vec![VmAction::Transfer {
+                    deposit: MINT_STORAGE_COST - storage_cost
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "yoctoNEAR to cover storage, attached 0")]
+    fn test_avatar_create_for_prepaid_without_deposit() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(contract.storage_balance_bounds().min.0 + MINT_STORAGE_COST)
+            .build());
+        contract.storage_deposit(Some(accounts(1)), None);
+        testing_env!(context.attached_deposit(0).build());
+        contract.avatar_create_for(accounts(1), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough storage balance")]
+    fn test_avatar_create_prepaid_short() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(contract.storage_balance_bounds().min.0)
+            .build());
+        contract.storage_deposit(None, None);
+        testing_env!(context.attached_deposit(0).build());
+        contract.avatar_create(None, None);
+    }
+
+    #[test]
+    fn test_approve_prepaid() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let deposit = contract.storage_balance_bounds().min.0 + MINT_STORAGE_COST;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .build());
+        contract.storage_deposit(None, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .build());
+        contract.avatar_create(None, None);
+        let token_id = contract.avatar_id_of(accounts(0)).unwrap();
+        let total = storage_total(&contract, accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+        assert!(contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+        let approved_total = storage_total(&contract, accounts(0));
+        assert!(approved_total < total);
+
+        // the revoked approval is credited back rather than transferred
+        let initial_storage_usage = env::storage_usage();
+        contract.nft_revoke(token_id.clone(), accounts(1));
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), None));
+        let storage_free = initial_storage_usage - env::storage_usage();
+        assert!(storage_free > 0);
+        assert_eq!(
+            approved_total + env::storage_byte_cost() * Balance::from(storage_free),
+            storage_total(&contract, accounts(0))
+        );
+        assert!(get_created_receipts().is_empty());
+
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+        contract.nft_approve(token_id.clone(), accounts(2), None);
+        let approved_total = storage_total(&contract, accounts(0));
+        let initial_storage_usage = env::storage_usage();
+        contract.nft_revoke_all(token_id.clone());
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), None));
+        assert!(!contract.nft_is_approved(token_id, accounts(2), None));
+        let storage_free = initial_storage_usage - env::storage_usage();
+        assert_eq!(
+            approved_total + env::storage_byte_cost() * Balance::from(storage_free),
+            storage_total(&contract, accounts(0))
+        );
+        assert!(get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn test_storage_unregister_force() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let deposit = contract.storage_balance_bounds().min.0 + MINT_STORAGE_COST;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .build());
+        contract.storage_deposit(None, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .build());
+        contract.avatar_create(None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        assert!(contract.storage_unregister(Some(true)));
+        assert!(!contract.avatar_exist(accounts(0)));
+        assert_eq!(
+            get_created_receipts().last().unwrap().actions,
+            vec![VmAction::Transfer { deposit }]
+        );
+    }
+
+    #[test]
+    fn test_avatar_upload() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let media = contract.avatar_upload(
+            "<svg viewBox=\"0 0 1 1\" onload=\"alert(1)\"><script>alert(1)</script><rect/></svg>"
+                .to_string(),
+        );
+        let svg = "<svg viewBox=\"0 0 1 1\"><rect/></svg>";
+        assert_eq!(pack_data_image(svg.as_bytes(), MediaType::Svg), media);
+        assert_eq!(
+            Some(token_id_of(svg.as_bytes())),
+            contract.avatar_id_of(accounts(1))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Svg must be at most 16384 bytes")]
+    fn test_avatar_upload_too_large() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let svg = format!("<svg>{}</svg>", " ".repeat(AVATAR_UPLOAD_MAX));
+        contract.avatar_upload(svg);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid svg")]
+    fn test_avatar_upload_invalid() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        contract.avatar_upload("<html><body/></html>".to_string());
+    }
+
+    #[test]
+    fn test_avatar_upload_image() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let data = b"GIF89a\x01\x00\x01\x00".to_vec();
+        let media = contract.avatar_upload_image(Base64VecU8(data.clone()));
+        assert_eq!(pack_data_image(&data, MediaType::Gif), media);
+        assert_eq!(
+            format!("data:image/gif;base64,{}", base64::encode(&data)),
+            contract.avatar_of(accounts(1), None)
+        );
+        let token_id = contract.avatar_id_of(accounts(1)).unwrap();
+        assert_eq!(token_id_of(&data), token_id);
+        assert_eq!(Some(MediaType::Gif), contract.avatar_media_type(token_id));
+
+        let svg = b"<svg onload=\"alert(1)\"><rect/></svg>".to_vec();
+        let media = contract.avatar_upload_image(Base64VecU8(svg));
+        assert_eq!(
+            pack_data_image(b"<svg><rect/></svg>", MediaType::Svg),
+            media
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported media type")]
+    fn test_avatar_upload_image_unsupported() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        contract.avatar_upload_image(Base64VecU8(b"BM\x00\x00".to_vec()));
+    }
+
+    #[test]
+    fn test_avatar_upload_ipfs() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let data = b"\x89PNG\r\n\x1a\n";
+        let cid = token_id_of(data);
+        let media_hash = Base64VecU8(env::sha256(data));
+        let media = contract.avatar_upload_ipfs(cid.clone(), media_hash, MediaType::Png);
+        assert_eq!(format!("ipfs://{}", cid), media);
+        assert_eq!(Some(cid.clone()), contract.avatar_id_of(accounts(1)));
+        assert_eq!(
+            Some(MediaType::Png),
+            contract.avatar_media_type(cid.clone())
+        );
+        assert_eq!(
+            format!("https://ipfs.io/ipfs/{}", cid),
+            contract.avatar_of(accounts(1), None)
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.update_ipfs_gateway("https://dweb.link/ipfs/".to_string());
+        assert_eq!(
+            format!("https://dweb.link/ipfs/{}", cid),
+            contract.avatar_of_size(accounts(1), 64, None)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Media hash does not match CID")]
+    fn test_avatar_upload_ipfs_hash_mismatch() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let cid = token_id_of(b"avatar");
+        let media_hash = Base64VecU8(env::sha256(b"other"));
+        contract.avatar_upload_ipfs(cid, media_hash, MediaType::Svg);
+    }
+
+    fn payout(amounts: &[(AccountId, u128)]) -> Payout {
+        Payout {
+            payout: amounts
+                .iter()
+                .map(|(account_id, amount)| (account_id.clone(), U128(*amount)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_nft_payout() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Neatar::new();
+        contract.update_royalty(Royalty {
+            treasury_id: accounts(3),
+            treasury: 250,
+            creator: 500,
+        });
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.avatar_upload("<svg><rect/></svg>".to_string());
+        let token_id = contract.avatar_id_of(accounts(1)).unwrap();
+        assert_eq!(
+            payout(&[(accounts(3), 250), (accounts(1), 9750)]),
+            contract.nft_payout(token_id.clone(), U128(10_000), Some(2))
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        let sale = contract.nft_transfer_payout(
+            accounts(2),
+            token_id.clone(),
+            None,
+            None,
+            U128(10_000),
+            None,
+        );
+        assert_eq!(payout(&[(accounts(3), 250), (accounts(1), 9750)]), sale);
+        assert_eq!(
+            payout(&[(accounts(3), 250), (accounts(1), 500), (accounts(2), 9250)]),
+            contract.nft_payout(token_id, U128(10_000), None)
+        );
+
+        // identicons pay the treasury only
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .signer_account_id(accounts(1))
+            .build());
+        contract.avatar_create(None, None);
+        let token_id = contract.avatar_id_of(accounts(1)).unwrap();
+        assert_eq!(
+            payout(&[(accounts(3), 250), (accounts(1), 9750)]),
+            contract.nft_payout(token_id, U128(10_000), None)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Payout is longer than max_len_payout")]
+    fn test_nft_payout_max_len() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Neatar::new();
+        contract.update_royalty(Royalty {
+            treasury_id: accounts(3),
+            treasury: 250,
+            creator: 500,
+        });
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.avatar_upload("<svg><rect/></svg>".to_string());
+        let token_id = contract.avatar_id_of(accounts(1)).unwrap();
+        contract.nft_payout(token_id, U128(10_000), Some(1));
+    }
+
+    #[test]
+    fn test_avatar_soulbound() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let token_ids = create_avatars(&mut context, &mut contract, 1);
+        assert!(!contract.avatar_soulbound(token_ids[0].clone()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .random_seed(vec![2; 32])
+            .build());
+        contract.avatar_create(None, Some(true));
+        let token_id = contract.avatar_by_edition(2).unwrap().token_id;
+        assert!(contract.avatar_soulbound(token_id.clone()));
+        let extra = contract
+            .nft_token(token_id.clone())
+            .unwrap()
+            .metadata
+            .unwrap()
+            .extra;
+        assert!(extra.unwrap().contains("\"soulbound\":true"));
+
+        // regenerated avatars stay soulbound
+        contract.avatar_set_active(token_id);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .random_seed(vec![3; 32])
+            .build());
+        contract.avatar_regenerate(None);
+        let token_id = contract.avatar_id_of(accounts(0)).unwrap();
+        assert!(contract.avatar_soulbound(token_id));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.update_soulbound(true);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .random_seed(vec![4; 32])
+            .build());
+        contract.avatar_create(None, Some(false));
+        let token_id = contract.avatar_by_edition(4).unwrap().token_id;
+        assert!(contract.avatar_soulbound(token_id));
+    }
+
+    #[test]
+    #[should_panic(expected = "Soulbound avatar can't be transferred")]
+    fn test_transfer_soulbound() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.avatar_create(None, Some(true));
+        let token_id = contract.avatar_id_of(accounts(0)).unwrap();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        contract.nft_transfer(accounts(1), token_id, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Soulbound avatar can't be approved")]
+    fn test_approve_soulbound() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Neatar::new();
+        contract.update_soulbound(true);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.avatar_create(None, None);
+        let token_id = contract.avatar_id_of(accounts(0)).unwrap();
+        contract.nft_approve(token_id, accounts(1), None);
+    }
+
+    #[test]
+    fn test_migrate_v2() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        create_avatars(&mut context, &mut contract, 1);
+        let v2 = NeatarV2 {
+            token: contract.token,
+            metadata: contract.metadata,
+            active_tokens: contract.active_tokens,
+            commitments: contract.commitments,
+            fallback: contract.fallback,
+            layout: contract.layout,
+            storage_deposits: contract.storage_deposits,
+            account_storage_usage: contract.account_storage_usage,
+            title_template: contract.title_template,
+            minted: contract.minted,
+            editions: contract.editions,
+            ipfs_gateway: contract.ipfs_gateway,
+            royalty: contract.royalty,
+            royalties: contract.royalties,
+            soulbound: contract.soulbound,
+        };
+        env::state_write(&v2);
+        // states written before the version was stored
+        env::storage_remove(STATE_VERSION_KEY);
+        assert_eq!(2, Neatar::state_version());
+
+        let contract = Neatar::migrate();
+        assert_eq!(STATE_VERSION, Neatar::state_version());
+        assert_eq!(1, contract.avatar_minted());
+        assert!(contract.avatar_token_of(accounts(0)).is_some());
+        assert_eq!(accounts(0), contract.admin_owner());
+        assert!(contract.admin_operators(None, None).is_empty());
+    }
+
+    #[test]
+    fn test_migrate_current() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        create_avatars(&mut context, &mut contract, 1);
+        env::state_write(&contract);
+
+        let contract = Neatar::migrate();
+        assert_eq!(1, contract.avatar_minted());
+        assert_eq!(STATE_VERSION, Neatar::state_version());
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires the Upgrader role")]
+    fn test_migrate_without_role() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let contract = Neatar::new();
+        env::state_write(&contract);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        Neatar::migrate();
+    }
+
+    #[test]
+    fn test_admin_roles() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Neatar::new();
+        assert!(contract.admin_grant_role(accounts(1), Role::Minter));
+        assert!(contract.admin_grant_role(accounts(1), Role::Moderator));
+        assert!(!contract.admin_grant_role(accounts(1), Role::Minter));
+        assert!(contract.admin_grant_role(accounts(2), Role::Upgrader));
+        assert_eq!(
+            vec![
+                Operator {
+                    account_id: accounts(1),
+                    roles: vec![Role::Minter, Role::Moderator],
+                },
+                Operator {
+                    account_id: accounts(2),
+                    roles: vec![Role::Upgrader],
+                },
+            ],
+            contract.admin_operators(None, None)
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.avatar_create_for(accounts(3), None, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .build());
+        contract.avatar_burn_for(accounts(3), None);
+        assert!(!contract.avatar_exist(accounts(3)));
+
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        assert!(contract.admin_revoke_role(accounts(1), Role::Minter));
+        assert!(!contract.admin_revoke_role(accounts(1), Role::Minter));
+        assert!(contract.admin_revoke_role(accounts(2), Role::Upgrader));
+        assert_eq!(vec![Role::Moderator], contract.admin_roles_of(accounts(1)));
+        assert_eq!(1, contract.admin_operators(None, None).len());
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires the Minter role")]
+    fn test_avatar_create_for_without_role() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Neatar::new();
+        contract.admin_grant_role(accounts(1), Role::Moderator);
+        testing_env!(context
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.avatar_create_for(accounts(1), None, None);
+    }
+
+    #[test]
+    fn test_admin_transfer_ownership() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Neatar::new();
+        assert_eq!(accounts(0), contract.admin_owner());
+        contract.admin_transfer_ownership(accounts(1));
+        assert_eq!(accounts(1), contract.admin_owner());
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.update_fallback(Fallback::Logo);
+        assert!(contract.admin_grant_role(accounts(2), Role::Minter));
+        contract.admin_transfer_ownership(accounts(2));
+        assert_eq!(accounts(2), contract.admin_owner());
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn test_update_royalty_without_yocto() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        contract.update_royalty(Royalty {
+            treasury_id: accounts(1),
+            treasury: 100,
+            creator: 0,
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Only contract owner")]
+    fn test_admin_grant_role_not_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Neatar::new();
+        contract.admin_grant_role(accounts(1), Role::Minter);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.admin_grant_role(accounts(2), Role::Minter);
+    }
+
+    #[test]
+    fn test_avatar_hash_of() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        assert_eq!(None, contract.avatar_hash_of(accounts(0)));
+        assert_eq!(None, contract.avatar_token_of(accounts(0)));
+        create_avatars(&mut context, &mut contract, 1);
+
+        let token = contract.avatar_token_of(accounts(0)).unwrap();
+        let full = contract.nft_token(token.token_id.clone()).unwrap();
+        let metadata = token.metadata.unwrap();
+        assert_eq!(None, metadata.media);
+        assert_eq!(full.metadata.unwrap().media_hash, metadata.media_hash);
+        assert_eq!(metadata.media_hash, contract.avatar_hash_of(accounts(0)));
+    }
+
+    #[test]
+    fn test_avatars_of() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        create_avatars(&mut context, &mut contract, 1);
+
+        let avatars = contract.avatars_of(vec![accounts(1), accounts(0)], None);
+        assert_eq!(
+            vec![
+                AccountAvatar {
+                    account_id: accounts(1),
+                    avatar: contract.identicon_of(accounts(1)),
+                    exist: false,
+                },
+                AccountAvatar {
+                    account_id: accounts(0),
+                    avatar: contract.avatar_of(accounts(0), None),
+                    exist: true,
+                },
+            ],
+            avatars
+        );
+        let avatars = contract.avatars_of(vec![accounts(1)], Some(Fallback::Logo));
+        assert_eq!(
+            contract.avatar_of(accounts(1), Some(Fallback::Logo)),
+            avatars[0].avatar
+        );
+    }
+
+    #[test]
+    fn test_avatars_of_worst_case() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let account_ids: Vec<AccountId> = (0..AVATARS_OF_MAX)
+            .map(|index| AccountId::new_unchecked(format!("account{:02}.near", index)))
+            .collect();
+        for (index, account_id) in account_ids.iter().enumerate() {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(10u128.pow(24))
+                .predecessor_account_id(account_id.clone())
+                .build());
+            let head = format!("<svg><path id=\"p{:02}\" d=\"M0 0", index);
+            let tail = "\"/></svg>";
+            let fill = "h1".repeat((AVATAR_UPLOAD_MAX - head.len() - tail.len()) / 2);
+            let svg = format!("{}{}{}", head, fill, tail);
+            contract.avatar_upload(svg);
+        }
+
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        let used_gas = env::used_gas();
+        let avatars = contract.avatars_of(account_ids, None);
+        let used_gas = env::used_gas() - used_gas;
+        assert!(avatars.iter().all(|avatar| avatar.exist));
+        // measured 1096551 bytes, a quarter of the 4 MiB returned data limit,
+        // and 27 TGas of host calls, wasm execution isn't counted by the mock
+        let returned = serde_json::to_vec(&avatars).unwrap();
+        assert!(returned.len() < 4 * 1024 * 1024 / 2);
+        assert!(used_gas < Gas(100_000_000_000_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "At most 50 accounts per call")]
+    fn test_avatars_of_limit() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let contract = Neatar::new();
+        contract.avatars_of(vec![accounts(1); AVATARS_OF_MAX + 1], None);
+    }
+
+    #[test]
+    fn test_avatar_burn() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .signer_account_id(accounts(0))
+            .build());
+
+        contract.avatar_create(None, None);
+        assert_eq!(1082, contract.avatar_of(accounts(0), None).len());
+        contract.avatar_burn();
+        assert_eq!(
+            614,
+            contract.avatar_of(accounts(0), Some(Fallback::Logo)).len()
+        );
+    }
+
+    #[test]
+    fn test_ft_burn_keeps_other_tokens() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        let token_ids = create_avatars(&mut context, &mut contract, 3);
+        assert_eq!(U128(3), contract.nft_supply_for_owner(accounts(0)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.ft_burn(token_ids[1].clone());
+
+        assert_eq!(U128(2), contract.nft_supply_for_owner(accounts(0)));
+        assert_eq!(U128(2), contract.nft_total_supply());
+        let mut owned: Vec<TokenId> = contract
+            .nft_tokens_for_owner(accounts(0), None, None)
+            .into_iter()
+            .map(|token| token.token_id)
+            .collect();
+        owned.sort();
+        let mut expected = vec![token_ids[0].clone(), token_ids[2].clone()];
+        expected.sort();
+        assert_eq!(expected, owned);
+        assert_eq!(None, contract.nft_token(token_ids[1].clone()));
+        assert!(contract.avatar_exist(accounts(0)));
+
+        contract.ft_burn(token_ids[0].clone());
+        contract.ft_burn(token_ids[2].clone());
+        assert_eq!(U128(0), contract.nft_supply_for_owner(accounts(0)));
+        assert!(contract
+            .nft_tokens_for_owner(accounts(0), None, None)
+            .is_empty());
+        assert!(!contract.avatar_exist(accounts(0)));
+    }
+
+    #[test]
+    fn test_avatar_set_active() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let token_ids = create_avatars(&mut context, &mut contract, 3);
+        assert_eq!(
+            Some(token_ids[0].clone()),
+            contract.avatar_id_of(accounts(0))
+        );
+
+        contract.avatar_set_active(token_ids[2].clone());
+        assert_eq!(
+            Some(token_ids[2].clone()),
+            contract.avatar_id_of(accounts(0))
+        );
+        let media = contract
+            .nft_token(token_ids[2].clone())
+            .unwrap()
+            .metadata
+            .unwrap()
+            .media
+            .unwrap();
+        assert_eq!(
+            format!("data:image/{}", media),
+            contract.avatar_of(accounts(0), None)
+        );
+
+        // burning another token keeps the choice
+        contract.ft_burn(token_ids[1].clone());
+        assert_eq!(
+            Some(token_ids[2].clone()),
+            contract.avatar_id_of(accounts(0))
+        );
+        // burning the active token picks the remaining one
+        contract.ft_burn(token_ids[2].clone());
+        assert_eq!(
+            Some(token_ids[0].clone()),
+            contract.avatar_id_of(accounts(0))
+        );
+        contract.ft_burn(token_ids[0].clone());
+        assert_eq!(None, contract.avatar_id_of(accounts(0)));
+    }
+
+    #[test]
+    fn test_avatar_create_keeps_active() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let token_ids = create_avatars(&mut context, &mut contract, 2);
+        contract.avatar_set_active(token_ids[1].clone());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .random_seed(vec![3; 32])
+            .build());
+        contract.avatar_create(None, None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .random_seed(vec![4; 32])
+            .build());
+        contract.avatar_create_for(accounts(0), None, None);
+        assert_eq!(U128(4), contract.nft_supply_for_owner(accounts(0)));
+        assert_eq!(
+            Some(token_ids[1].clone()),
+            contract.avatar_id_of(accounts(0))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner")]
+    fn test_avatar_set_active_not_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let token_ids = create_avatars(&mut context, &mut contract, 1);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.avatar_set_active(token_ids[0].clone());
+    }
+
+    #[test]
+    fn test_transfer_active() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let token_ids = create_avatars(&mut context, &mut contract, 2);
+        contract.avatar_set_active(token_ids[0].clone());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_transfer(accounts(1), token_ids[0].clone(), None, None);
+        assert_eq!(
+            Some(token_ids[1].clone()),
+            contract.avatar_id_of(accounts(0))
+        );
+        assert_eq!(
+            Some(token_ids[0].clone()),
+            contract.avatar_id_of(accounts(1))
+        );
+
+        // the receiver keeps the avatar already chosen
+        contract.nft_transfer(accounts(1), token_ids[1].clone(), None, None);
+        assert_eq!(None, contract.avatar_id_of(accounts(0)));
+        assert_eq!(
+            Some(token_ids[0].clone()),
+            contract.avatar_id_of(accounts(1))
+        );
+        assert_eq!(U128(2), contract.nft_supply_for_owner(accounts(1)));
+    }
+
+    #[test]
+    fn test_avatar_burn_for() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .signer_account_id(accounts(0))
+            .build());
+
+        contract.avatar_create_for(accounts(1), None, None);
+        let token_id = contract.avatar_id_of(accounts(1)).unwrap();
+
+        let initial_storage_usage = env::storage_usage();
+        testing_env!(context
+            .storage_usage(initial_storage_usage)
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.avatar_burn_for(accounts(1), Some(accounts(2)));
+        assert!(!contract.avatar_exist(accounts(1)));
+        assert_eq!(None, contract.nft_token(token_id.clone()));
+        assert_eq!(
+            format!(
+                r#"EVENT_JSON:{{"standard":"nep171","version":"1.0.0","event":"nft_burn","data":[{{"authorized_id":"{}","owner_id":"{}","token_ids":["{}"]}}]}}"#,
+                accounts(0),
+                accounts(1),
+                token_id
+            ),
+            get_logs()[0]
+        );
+        assert!(get_logs()[2].starts_with("storage free: "));
+
+        let storage_cost =
+            env::storage_byte_cost() * Balance::from(initial_storage_usage - env::storage_usage());
+        assert_eq!(
+            get_created_receipts(),
+            vec![Receipt {
+                receipt_indices: vec![],
+                receiver_id: accounts(2),
+                actions: vec![VmAction::Transfer {
+                    deposit: storage_cost
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_events_mint_and_transfer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.avatar_create(None, None);
+        let token_id = contract.avatar_id_of(accounts(0)).unwrap();
+        let logs = get_logs();
+        assert_eq!(3, logs.len());
+        assert_eq!(
+            format!(
+                r#"EVENT_JSON:{{"standard":"nep171","version":"1.0.0","event":"nft_mint","data":[{{"owner_id":"alice","token_ids":["{}"]}}]}}"#,
+                token_id
+            ),
+            logs[0]
+        );
+        assert_eq!(
+            format!(
+                r#"EVENT_JSON:{{"standard":"neatar","version":"1.0.0","event":"avatar_activate","data":[{{"account_id":"alice","token_id":"{}"}}]}}"#,
+                token_id
+            ),
+            logs[1]
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        contract.nft_transfer(
+            accounts(1),
+            token_id.clone(),
+            None,
+            Some("gift".to_string()),
+        );
+        assert_eq!(
+            vec![
+                format!("Transfer {} from alice to bob", token_id),
+                "Memo: gift".to_string(),
+                format!(
+                    r#"EVENT_JSON:{{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{{"old_owner_id":"alice","new_owner_id":"bob","token_ids":["{}"],"memo":"gift"}}]}}"#,
+                    token_id
+                ),
+                r#"EVENT_JSON:{"standard":"neatar","version":"1.0.0","event":"avatar_activate","data":[{"account_id":"alice","token_id":null}]}"#.to_string(),
+                format!(
+                    r#"EVENT_JSON:{{"standard":"neatar","version":"1.0.0","event":"avatar_activate","data":[{{"account_id":"bob","token_id":"{}"}}]}}"#,
+                    token_id
+                ),
+            ],
+            get_logs()
+        );
+    }
+
+    #[test]
+    fn test_avatar_regenerate() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let token_ids = create_avatars(&mut context, &mut contract, 1);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .random_seed(vec![42; 32])
+            .build());
+        let avatar = contract.avatar_regenerate(None);
+        let token_id = contract.avatar_id_of(accounts(0)).unwrap();
+        assert_ne!(token_ids[0], token_id);
+        assert_eq!(
+            format!("data:image/{}", avatar),
+            contract.avatar_of(accounts(0), None)
+        );
+        assert_eq!(None, contract.nft_token(token_ids[0].clone()));
+        assert_eq!(U128(1), contract.nft_supply_for_owner(accounts(0)));
+        assert_eq!(
+            &format!(
+                r#"EVENT_JSON:{{"standard":"neatar","version":"1.0.0","event":"avatar_regenerate","data":[{{"account_id":"alice","old_token_id":"{}","new_token_id":"{}"}}]}}"#,
+                token_ids[0], token_id
+            ),
+            get_logs().last().unwrap()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Not found avatar")]
+    fn test_avatar_burn_for_without_avatar() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        contract.avatar_burn_for(accounts(1), None);
+    }
+
+    #[test]
+    fn test_avatar_create_entropy() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .block_timestamp(1)
+            .random_seed(vec![1; 32])
+            .build());
+        let by_random = contract.avatar_create_for(accounts(0), Some(Entropy::Random), None);
+        let by_timestamp = contract.avatar_create_for(accounts(0), Some(Entropy::Timestamp), None);
+        assert_ne!(by_random, by_timestamp);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .block_timestamp(1)
+            .random_seed(vec![2; 32])
+            .build());
+        assert_ne!(
+            by_random,
+            contract.avatar_create_for(accounts(0), None, None)
+        );
+        assert_eq!(U128(3), contract.nft_supply_for_owner(accounts(0)));
+    }
+
+    #[test]
+    fn test_entropy_seed() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(1).random_seed(vec![1; 32]).build());
+        let timestamp = Entropy::Timestamp.seed(&accounts(0));
+        let random = Entropy::Random.seed(&accounts(0));
+
+        testing_env!(context.random_seed(vec![2; 32]).build());
+        assert_eq!(timestamp, Entropy::Timestamp.seed(&accounts(0)));
+        assert_ne!(random, Entropy::Random.seed(&accounts(0)));
+        assert_ne!(random, Entropy::Random.seed(&accounts(1)));
+    }
+
+    #[test]
+    fn test_avatar_create_identity() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let identicon = contract.identicon_of(accounts(0));
+        assert_ne!(identicon, contract.identicon_of(accounts(1)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .block_timestamp(1)
+            .random_seed(vec![1; 32])
+            .build());
+        contract.avatar_create(Some(Entropy::Identity), None);
+        assert_eq!(identicon, contract.avatar_of(accounts(0), None));
+        contract.avatar_burn();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .block_timestamp(2)
+            .random_seed(vec![2; 32])
+            .build());
+        contract.avatar_create(Some(Entropy::Identity), None);
+        assert_eq!(identicon, contract.avatar_of(accounts(0), None));
+    }
+
+    #[test]
+    #[should_panic(expected = "Timestamp entropy is available only via avatar_create_for")]
+    fn test_avatar_create_timestamp() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        contract.avatar_create(Some(Entropy::Timestamp), None);
+    }
+
+    #[test]
+    fn test_avatar_of_fallback() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let logo = format!(
+            "data:image/{}",
+            pack_data_image(LOGO.as_bytes(), MediaType::Svg)
+        );
+
+        assert_eq!(Fallback::Identicon, contract.avatar_fallback());
+        assert_eq!(
+            contract.identicon_of(accounts(1)),
+            contract.avatar_of(accounts(1), None)
+        );
+        assert_ne!(
+            contract.avatar_of(accounts(1), None),
+            contract.avatar_of(accounts(2), None)
+        );
+        assert_eq!(logo, contract.avatar_of(accounts(1), Some(Fallback::Logo)));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.update_fallback(Fallback::Logo);
+        assert_eq!(logo, contract.avatar_of(accounts(1), None));
+        assert_eq!(
+            contract.identicon_of(accounts(1)),
+            contract.avatar_of(accounts(1), Some(Fallback::Identicon))
+        );
+    }
+
+    #[test]
+    fn test_avatar_layout() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        assert_eq!(Layout::Compact, contract.avatar_layout());
+        let compact = contract.identicon_of(accounts(1));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.update_layout(Layout::Full);
+        let full = contract.identicon_of(accounts(1));
+        assert_ne!(compact, full);
+        assert_eq!(
+            format!(
+                "data:image/{}",
+                pack_data_image(
+                    identicon::make(accounts(1).as_bytes(), Layout::Full).as_bytes(),
+                    MediaType::Svg
+                )
+            ),
+            full
+        );
+
+        // fractional coordinates of the full layout take more storage
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINT_STORAGE_COST)
+            .build());
+        let media = contract.avatar_create_for(accounts(1), Some(Entropy::Identity), None);
+        assert_eq!(full, format!("data:image/{}", media));
+    }
+
+    #[test]
+    fn test_token_id_of() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let token_id = token_id_of(LOGO.as_bytes());
+        assert!(token_id.starts_with("bafkrei"));
+        assert_eq!(default_token().token_id, token_id);
+    }
+
+    #[test]
+    fn test_token_title() {
+        let token_id = "bafkreieyck4x2tujwtvmdu4dltjmff67khqviaewzixidj5zoa2sjrc62y";
+        assert_eq!("#eyck...c62y", token_title(TITLE_TEMPLATE, token_id, None));
+        assert_eq!(
+            "Neatar eyck...c62y",
+            token_title("Neatar {id}", token_id, Some(7))
+        );
+        assert_eq!("#short", token_title(TITLE_TEMPLATE, "short", None));
+        assert_eq!(
+            "Neatar #7",
+            token_title("Neatar #{edition}", token_id, Some(7))
+        );
+    }
+
+    #[test]
+    fn test_migrate_titles() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let token_ids = create_avatars(&mut context, &mut contract, 3);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.update_title_template("Neatar {id}".to_string());
+        assert_eq!(2, contract.migrate_titles(None, Some(2)));
+        assert_eq!(1, contract.migrate_titles(Some(U128(2)), Some(2)));
+        for token_id in token_ids {
+            let title = contract
+                .nft_token(token_id.clone())
+                .unwrap()
+                .metadata
+                .unwrap()
+                .title;
+            assert_eq!(Some(token_title("Neatar {id}", &token_id, None)), title);
+        }
+    }
+
+    #[test]
+    fn test_avatar_edition() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        let token_ids = create_avatars(&mut context, &mut contract, 3);
+        assert_eq!(3, contract.avatar_minted());
+
+        for (index, token_id) in token_ids.iter().enumerate() {
+            let edition = index as u64 + 1;
+            assert_eq!(Some(edition), contract.avatar_edition(token_id.clone()));
+            let token = contract.avatar_by_edition(edition).unwrap();
+            assert_eq!(token_id, &token.token_id);
+            assert_eq!(
+                Some(format!(
+                    "{{\"edition\":{},\"media_type\":\"svg\",\"soulbound\":false}}",
+                    edition
+                )),
+                token.metadata.unwrap().extra
+            );
+        }
+
+        contract.ft_burn(token_ids[1].clone());
+        assert!(contract.avatar_by_edition(2).is_none());
+        assert_eq!(None, contract.avatar_edition(token_ids[1].clone()));
+        assert_eq!(3, contract.avatar_minted());
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.update_title_template("Neatar #{edition}".to_string());
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .random_seed(vec![4; 32])
+            .build());
+        contract.avatar_create(None, None);
+        let token = contract.avatar_by_edition(4).unwrap();
+        assert_eq!(Some("Neatar #4".to_string()), token.metadata.unwrap().title);
+    }
+
+    /// Writes the state of the first release holding avatars of `accounts(0)`
+    /// minted at `timestamps`, returns their token ids.
+    fn write_state_v1(context: &mut VMContextBuilder, timestamps: &[u64]) -> Vec<TokenId> {
+        let mut token = NonFungibleToken::new(
+            StorageKey::NonFungibleToken,
+            accounts(0),
+            Some(StorageKey::TokenMetadata),
+            Some(StorageKey::Enumeration),
+            Some(StorageKey::Approval),
+        );
+        let mut token_ids = Vec::new();
+        for (index, timestamp) in timestamps.iter().enumerate() {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_STORAGE_COST)
+                .block_timestamp(*timestamp)
+                .build());
+            let svg = identicon::make(&[index as u8], Layout::Compact);
+            let minted = new_token(svg, Some(accounts(0)), TITLE_TEMPLATE, None);
+            token.internal_mint(minted.token_id.clone(), accounts(0), minted.metadata);
+            token_ids.push(minted.token_id);
+        }
+        let metadata = LazyOption::new(StorageKey::Metadata, Some(&new_nft_metadata()));
+        // NeatarV1 only reads, its fields are written in the same order
+        env::state_write(&(token, metadata));
+        token_ids
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let token_ids = write_state_v1(&mut context, &[30, 10, 20]);
+        assert_eq!(1, Neatar::state_version());
+
+        let mut contract = Neatar::migrate();
+        env::state_write(&contract);
+        assert_eq!(STATE_VERSION, Neatar::state_version());
+        // V1 goes through V2 to the current layout
+        assert!(contract.account_storage_usage > 0);
+        assert_eq!(accounts(0), contract.admin_owner());
+        // editions follow the mint order of the tokens minted before them
+        assert_eq!(3, contract.avatar_minted());
+        assert_eq!(Some(2), contract.avatar_edition(token_ids[2].clone()));
+        let token = contract.avatar_by_edition(1).unwrap();
+        assert_eq!(token_ids[1], token.token_id);
+        assert_eq!(
+            Some("{\"edition\":1,\"media_type\":\"svg\",\"soulbound\":false}".to_string()),
+            token.metadata.unwrap().extra
+        );
+        assert_eq!(Some(3), contract.avatar_edition(token_ids[0].clone()));
+
+        let new_token_ids = create_avatars(&mut context, &mut contract, 1);
+        assert_eq!(4, contract.avatar_minted());
+        assert_eq!(Some(4), contract.avatar_edition(new_token_ids[0].clone()));
+    }
+
+    #[test]
+    fn test_resize_svg() {
+        assert_eq!(
+            "<svg width=\"16\" height=\"16\" viewBox=\"0 0 1 1\"><rect stroke-width=\"1\"/></svg>",
+            resize_svg(
+                "<svg viewBox=\"0 0 1 1\"><rect stroke-width=\"1\"/></svg>",
+                16
+            )
+        );
+        assert_eq!(
+            "<?xml?><svg width=\"512\" height=\"512\"   viewBox=\"0 0 120 120\">",
+            resize_svg(
+                "<?xml?><svg width=\"120\" height=\"120\" viewBox=\"0 0 120 120\">",
+                512
+            )
+        );
+        assert_eq!(
+            "<svg width=\"64\" height=\"64\" viewBox=\"0 0 100 50\"   fill=\"red\">",
+            resize_svg("<svg width=\"100px\" height=\"50\" fill=\"red\">", 64)
+        );
+        assert_eq!(
+            "<svg width=\"64\" height=\"64\"  >",
+            resize_svg("<svg width=\"100%\" height=\"100%\">", 64)
+        );
+    }
+
+    #[test]
+    fn test_avatar_of_size_upload() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        contract.avatar_upload("<svg width=\"100\" height=\"100\"><rect/></svg>".to_string());
+        let avatar = contract.avatar_of_size(accounts(1), 64, None);
+        let (_, svg) = unpack_data_image(avatar.trim_start_matches("data:image/")).unwrap();
+        assert_eq!(
+            "<svg width=\"64\" height=\"64\" viewBox=\"0 0 100 100\"  ><rect/></svg>",
+            String::from_utf8(svg).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_avatar_of_size() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        let identicon = identicon::make_sized(accounts(1).as_bytes(), Layout::Compact, 16);
+        assert_eq!(
+            format!(
+                "data:image/{}",
+                pack_data_image(identicon.as_bytes(), MediaType::Svg)
+            ),
+            contract.avatar_of_size(accounts(1), 16, None)
+        );
+        let logo = contract.avatar_of_size(accounts(1), 512, Some(Fallback::Logo));
+        let (_, logo) = unpack_data_image(logo.trim_start_matches("data:image/")).unwrap();
+        assert!(String::from_utf8(logo)
+            .unwrap()
+            .starts_with("<svg width=\"512\" height=\"512\""));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .build());
+        let media = contract.avatar_create(None, None);
+        let (_, svg) = unpack_data_image(&media).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert_eq!(
+            format!(
+                "data:image/{}",
+                pack_data_image(resize_svg(&svg, 128).as_bytes(), MediaType::Svg)
+            ),
+            contract.avatar_of_size(accounts(0), 128, None)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Size must be from 1 to 2048")]
+    fn test_avatar_of_size_limit() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let contract = Neatar::new();
+        contract.avatar_of_size(accounts(1), 4096, None);
+    }
+
+    #[test]
+    fn test_avatar_commit_reveal() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        let secret = b"secret".to_vec();
+        let initial_storage_usage = env::storage_usage();
+        testing_env!(context
+            .storage_usage(initial_storage_usage)
+            .attached_deposit(COMMIT_STORAGE_COST)
+            .block_index(1)
+            .build());
+        contract.avatar_commit(Base64VecU8(env::sha256(&secret)));
+        let commit_cost =
+            env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert_eq!(
+            vec![VmAction::Transfer {
+                deposit: COMMIT_STORAGE_COST - commit_cost
+            }],
+            get_created_receipts()[0].actions
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .block_index(2)
+            .random_seed(vec![7; 32])
+            .build());
+        let avatar = contract.avatar_reveal(Base64VecU8(secret));
+        assert_eq!(1071, avatar.len());
+        assert!(contract.avatar_exist(accounts(0)));
+        assert!(contract.commitments.get(&accounts(0)).is_none());
+        // the storage of the commitment is given back
+        assert_eq!(
+            vec![VmAction::Transfer {
+                deposit: commit_cost
+            }],
+            get_created_receipts()[0].actions
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "yoctoNEAR to cover storage, attached 0")]
+    fn test_avatar_commit_without_deposit() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+        contract.avatar_commit(Base64VecU8(env::sha256(b"secret")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Reveal in a later block than commit")]
+    fn test_avatar_reveal_same_block() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_index(1).build());
+        let mut contract = Neatar::new();
+
+        let secret = b"secret".to_vec();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(COMMIT_STORAGE_COST)
+            .build());
+        contract.avatar_commit(Base64VecU8(env::sha256(&secret)));
+        contract.avatar_reveal(Base64VecU8(secret));
+    }
+
+    #[test]
+    #[should_panic(expected = "Secret does not match commitment")]
+    fn test_avatar_reveal_wrong_secret() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_index(1).build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(COMMIT_STORAGE_COST)
+            .build());
+        contract.avatar_commit(Base64VecU8(env::sha256(b"secret")));
+        testing_env!(context.block_index(2).build());
+        contract.avatar_reveal(Base64VecU8(b"guess".to_vec()));
+    }
+
+    #[test]
+    fn test_avatar_exist() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Neatar::new();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .block_timestamp(42)
+            .predecessor_account_id(accounts(0))
+            .signer_account_id(accounts(0))
+            .build());
+
+        assert!(!contract.avatar_exist(accounts(0)));
+        assert_eq!(None, contract.avatar_id_of(accounts(0)));
+        assert_eq!(None, contract.avatar_issued_at(accounts(0)));
+
+        contract.avatar_create(None, None);
+        let token_id = contract
+            .nft_tokens_for_owner(accounts(0), None, None)
+            .first()
+            .cloned()
+            .unwrap()
+            .token_id;
+        assert!(contract.avatar_exist(accounts(0)));
+        assert_eq!(Some(token_id), contract.avatar_id_of(accounts(0)));
+        assert_eq!(
+            Some("42".to_string()),
+            contract.avatar_issued_at(accounts(0))
+        );
+        assert!(!contract.avatar_exist(accounts(1)));
 
-        contract.avatar_create();
-        assert_eq!(1082, contract.avatar_of(accounts(0)).len());
         contract.avatar_burn();
-        assert_eq!(614, contract.avatar_of(accounts(0)).len());
+        assert!(!contract.avatar_exist(accounts(0)));
+        assert_eq!(None, contract.avatar_id_of(accounts(0)));
     }
 
     #[test]
@@ -357,7 +3627,7 @@ mod unit {
             .attached_deposit(MINT_STORAGE_COST)
             .predecessor_account_id(accounts(0))
             .build());
-        contract.avatar_create();
+        contract.avatar_create(None, None);
         let token_id = contract
             .nft_tokens_for_owner(accounts(0), None, None)
             .first()
@@ -399,7 +3669,7 @@ mod unit {
             .attached_deposit(MINT_STORAGE_COST)
             .predecessor_account_id(accounts(0))
             .build());
-        contract.avatar_create();
+        contract.avatar_create(None, None);
         let token = contract
             .nft_tokens_for_owner(accounts(0), None, None)
             .first()
@@ -433,7 +3703,7 @@ mod unit {
             .attached_deposit(MINT_STORAGE_COST)
             .predecessor_account_id(accounts(0))
             .build());
-        contract.avatar_create();
+        contract.avatar_create(None, None);
         let token = contract
             .nft_tokens_for_owner(accounts(0), None, None)
             .first()
@@ -474,7 +3744,7 @@ mod unit {
             .attached_deposit(MINT_STORAGE_COST)
             .predecessor_account_id(accounts(0))
             .build());
-        contract.avatar_create();
+        contract.avatar_create(None, None);
         let token = contract
             .nft_tokens_for_owner(accounts(0), None, None)
             .first()
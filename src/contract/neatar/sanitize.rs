@@ -0,0 +1,184 @@
+//! Sanitization of user supplied SVG, keeps only markup that is safe to
+//! show in an `<img>` and does not reach out of the document.
+use svg::node::element::tag::Type;
+use svg::node::Attributes;
+use svg::parser::{Event, Parser};
+
+/// Elements removed together with their content.
+const DROPPED_ELEMENTS: [&str; 11] = [
+    "script",
+    "foreignobject",
+    "style",
+    "iframe",
+    "object",
+    "embed",
+    "audio",
+    "video",
+    "canvas",
+    "handler",
+    "listener",
+];
+
+/// Elements able to change attributes of other elements after load.
+const ANIMATION_ELEMENTS: [&str; 4] = ["set", "animate", "animatemotion", "animatetransform"];
+
+/// Data URIs allowed in `href`, besides local `#id` references.
+const DATA_IMAGES: [&str; 4] = [
+    "data:image/png;",
+    "data:image/jpeg;",
+    "data:image/gif;",
+    "data:image/webp;",
+];
+
+/// Name without the namespace prefix, lowercased.
+fn local_name(name: &str) -> String {
+    name.rsplit(':').next().unwrap_or(name).to_lowercase()
+}
+
+fn is_event_handler(name: &str) -> bool {
+    local_name(name).starts_with("on")
+}
+
+fn is_local_href(value: &str) -> bool {
+    let value = value.trim().to_lowercase();
+    value.starts_with('#') || DATA_IMAGES.iter().any(|prefix| value.starts_with(prefix))
+}
+
+/// Whether the value has no escapes that could hide a reference
+/// and every `url(` in it points inside the document.
+fn is_safe_value(value: &str) -> bool {
+    let value = value.to_lowercase();
+    if value.contains("&#") || value.contains('\\') || value.contains("javascript:") {
+        return false;
+    }
+    value.match_indices("url(").all(|(i, _)| {
+        value[i + "url(".len()..]
+            .trim_start_matches(&[' ', '\'', '"'][..])
+            .starts_with('#')
+    })
+}
+
+fn is_dropped(name: &str, attributes: &Attributes) -> bool {
+    let name = local_name(name);
+    if DROPPED_ELEMENTS.contains(&name.as_str()) {
+        return true;
+    }
+    ANIMATION_ELEMENTS.contains(&name.as_str())
+        && attributes.get("attributeName").map_or(false, |target| {
+            local_name(target) == "href" || is_event_handler(target)
+        })
+}
+
+fn write_tag(output: &mut String, name: &str, kind: Type, attributes: &Attributes) {
+    if kind == Type::End {
+        output.push_str(&format!("</{}>", name));
+        return;
+    }
+    let mut kept: Vec<(&String, &str)> = attributes
+        .iter()
+        .map(|(name, value)| (name, &**value))
+        .filter(|(name, value)| {
+            !is_event_handler(name)
+                && (local_name(name) != "href" || is_local_href(value))
+                && is_safe_value(value)
+        })
+        .collect();
+    // attributes come from a hash map, the order is fixed to keep the output stable
+    kept.sort();
+    output.push('<');
+    output.push_str(name);
+    for (name, value) in kept {
+        output.push_str(&format!(" {}=\"{}\"", name, value.replace('"', "&quot;")));
+    }
+    output.push_str(if kind == Type::Empty { "/>" } else { ">" });
+}
+
+/// Sanitized copy of the svg, `None` if it is malformed or its root is not `svg`.
+pub fn sanitize_svg(svg: &str) -> Option<String> {
+    let mut output = String::with_capacity(svg.len());
+    let mut open: Vec<&str> = Vec::new();
+    let mut dropped_depth = 0usize;
+    let mut has_root = false;
+    for event in Parser::new(svg) {
+        match event {
+            Event::Error(_) => return None,
+            Event::Tag(name, kind, attributes) => {
+                match kind {
+                    Type::Start | Type::Empty if open.is_empty() => {
+                        if has_root || local_name(name) != "svg" {
+                            return None;
+                        }
+                        has_root = true;
+                    }
+                    Type::End if open.last() != Some(&name) => return None,
+                    _ => {}
+                }
+                let dropped = dropped_depth > 0 || is_dropped(name, &attributes);
+                match kind {
+                    Type::Start => {
+                        open.push(name);
+                        if dropped {
+                            dropped_depth += 1;
+                        }
+                    }
+                    Type::End => {
+                        open.pop();
+                        dropped_depth = dropped_depth.saturating_sub(1);
+                    }
+                    Type::Empty => {}
+                }
+                if !dropped {
+                    write_tag(&mut output, name, kind, &attributes);
+                }
+            }
+            Event::Text(text) if dropped_depth == 0 && !open.is_empty() => output.push_str(text),
+            // comments, declarations (doctype, CDATA) and instructions are not kept
+            _ => {}
+        }
+    }
+    if has_root && open.is_empty() {
+        Some(output)
+    } else {
+        None
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod unit {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_svg() {
+        let svg = r##"<?xml version="1.0"?>
+<svg xmlns="http://www.w3.org/2000/svg" onload="alert(1)" viewBox="0 0 2 2"><!-- note -->
+<script>alert(1)</script><style>@import url(https://example.com/a.css);</style>
+<foreignObject><div>html</div></foreignObject>
+<defs><linearGradient id="g"><stop offset="0" stop-color="red"/></linearGradient></defs>
+<rect fill="url(#g)" width="2" height="2" onclick="alert(1)"/>
+<circle fill="url(https://example.com/x)" r="1"/>
+<use href="#g"/><use xlink:href="https://example.com/x.svg#a"/>
+<a href="javascript:alert(1)"><text>hi</text></a>
+<set attributeName="href" to="javascript:alert(1)"/>
+</svg>"##;
+        assert_eq!(
+            Some(
+                concat!(
+                    r##"<svg viewBox="0 0 2 2" xmlns="http://www.w3.org/2000/svg">"##,
+                    r##"<defs><linearGradient id="g"><stop offset="0" stop-color="red"/></linearGradient></defs>"##,
+                    r##"<rect fill="url(#g)" height="2" width="2"/><circle r="1"/>"##,
+                    r##"<use href="#g"/><use/><a><text>hi</text></a></svg>"##
+                )
+                .to_string()
+            ),
+            sanitize_svg(svg)
+        );
+    }
+
+    #[test]
+    fn test_sanitize_svg_invalid() {
+        assert_eq!(None, sanitize_svg("<html></html>"));
+        assert_eq!(None, sanitize_svg("<svg><g></svg>"));
+        assert_eq!(None, sanitize_svg("<svg></svg><svg></svg>"));
+        assert_eq!(None, sanitize_svg("<svg><g>"));
+    }
+}